@@ -58,6 +58,17 @@ pub struct ChunkColumn<R: gfx::Resources> {
     pub biomes: [[BiomeId; SIZE]; SIZE]
 }
 
+impl<R: gfx::Resources> ChunkColumn<R> {
+    /// A column whose buffers have yet to be filled.
+    pub fn new(chunks: Vec<Chunk>, biomes: [[BiomeId; SIZE]; SIZE]) -> ChunkColumn<R> {
+        ChunkColumn {
+            chunks: chunks,
+            buffers: Array::from_fn(|_| RefCell::new(None)),
+            biomes: biomes
+        }
+    }
+}
+
 pub struct ChunkManager<R: gfx::Resources> {
     chunk_columns: HashMap<(i32, i32), ChunkColumn<R>>
 }
@@ -73,6 +84,61 @@ impl<R: gfx::Resources> ChunkManager<R> {
         self.chunk_columns.insert((x, z), c);
     }
 
+    pub fn has_chunk_column(&self, x: i32, z: i32) -> bool {
+        self.chunk_columns.contains_key(&(x, z))
+    }
+
+    /// Removes a column, handing it back so its buffers can be freed.
+    pub fn remove_chunk_column(&mut self, x: i32, z: i32) -> Option<ChunkColumn<R>> {
+        self.chunk_columns.remove(&(x, z))
+    }
+
+    /// The coordinates of the chunks in a column and the columns around
+    /// it, whose buffers have to be filled again when it's added or removed.
+    pub fn chunks_around_column(&self, x: i32, z: i32) -> Vec<[i32; 3]> {
+        let mut coords = vec![];
+        for dz in range(-1, 2) {
+            for dx in range(-1, 2) {
+                match self.chunk_columns.get(&(x + dx, z + dz)) {
+                    Some(c) => for y in range(0, c.chunks.len()) {
+                        coords.push([x + dx, y as i32, z + dz]);
+                    },
+                    None => {}
+                }
+            }
+        }
+        coords
+    }
+
+    /// The buffer of a chunk, with the chunks and biomes around it.
+    pub fn chunk_and_neighbors<'a>(&'a self, [x, y, z]: [i32; 3])
+        -> Option<(&'a RefCell<Option<Buffer<R>>>,
+                   [[[&'a Chunk; 3]; 3]; 3],
+                   [[Option<&'a [[BiomeId; SIZE]; SIZE]>; 3]; 3])>
+    {
+        let columns = [-1, 0, 1].map(
+                |dz| [-1, 0, 1].map(
+                    |dx| self.chunk_columns.get(&(x + dx, z + dz))
+                )
+            );
+        let central = match columns[1][1] {
+            Some(central) if y >= 0 && (y as usize) < central.chunks.len() => central,
+            _ => return None
+        };
+        let chunks = [-1, 0, 1].map(|dy| {
+            let y = y + dy;
+            columns.map(
+                |cz| cz.map(
+                    |cx| cx.and_then(
+                        |c| c.chunks.as_slice().get(y as usize)
+                    ).unwrap_or(EMPTY_CHUNK)
+                )
+            )
+        });
+        Some((&central.buffers[y as usize], chunks,
+              columns.map(|cz| cz.map(|cx| cx.map(|c| &c.biomes)))))
+    }
+
     pub fn each_chunk_and_neighbors<'a, F>(&'a self, mut f: F)
         where F: FnMut(/*coords:*/ [i32; 3],
                        /*buffer:*/ &'a RefCell<Option<Buffer<R>>>,
@@ -80,26 +146,11 @@ impl<R: gfx::Resources> ChunkManager<R> {
                        /*biomes:*/ [[Option<&'a [[BiomeId; SIZE]; SIZE]>; 3]; 3])
 
     {
-        for &(x, z) in self.chunk_columns.keys() {
-            let columns = [-1, 0, 1].map(
-                    |dz| [-1, 0, 1].map(
-                        |dx| self.chunk_columns.get(&(x + dx, z + dz))
-                    )
-                );
-            let central = columns[1][1].unwrap();
-            for y in range(0, central.chunks.len()) {
-                let chunks = [-1, 0, 1].map(|dy| {
-                    let y = y as i32 + dy;
-                    columns.map(
-                        |cz| cz.map(
-                            |cx| cx.and_then(
-                                |c| c.chunks.as_slice().get(y as usize)
-                            ).unwrap_or(EMPTY_CHUNK)
-                        )
-                    )
-                });
-                f([x, y as i32, z], &central.buffers[y], chunks,
-                  columns.map(|cz| cz.map(|cx| cx.map(|c| &c.biomes))))
+        for (&(x, z), c) in self.chunk_columns.iter() {
+            for y in range(0, c.chunks.len()) {
+                let coords = [x, y as i32, z];
+                let (buffer, chunks, biomes) = self.chunk_and_neighbors(coords).unwrap();
+                f(coords, buffer, chunks, biomes)
             }
         }
     }
@@ -0,0 +1,35 @@
+#![feature(box_syntax, collections, core, custom_attribute, old_io,
+    old_path, os, plugin, rustc_private, std_misc)]
+#![cfg_attr(test, feature(test))]
+#![plugin(gfx_macros)]
+
+extern crate draw_state;
+extern crate flate;
+extern crate gfx;
+extern crate gfx_voxel;
+extern crate time;
+extern crate vecmath;
+
+extern crate "rustc-serialize" as serialize;
+#[cfg(test)]
+extern crate test;
+
+// Reexport modules from gfx_voxel while stuff is moving
+// from Hematite to the library.
+pub use gfx_voxel::{ array, cube };
+
+pub mod chunk;
+pub mod shader;
+
+pub mod minecraft {
+    pub use self::data_1_8_pre2 as data;
+
+    mod data_1_8_pre2;
+    pub mod biome;
+    pub mod block_state;
+    pub mod lz4;
+    pub mod model;
+    pub mod nbt;
+    pub mod region;
+    pub mod world;
+}
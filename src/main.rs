@@ -1,15 +1,11 @@
-#![feature(box_syntax, collections, core, custom_attribute, old_io,
-    old_path, os, plugin, rustc_private, std_misc)]
-#![plugin(gfx_macros)]
+#![feature(collections, core, old_io, old_path, os, std_misc)]
 
 extern crate camera_controllers;
 extern crate event;
-extern crate flate;
 extern crate fps_counter;
 extern crate gfx;
 extern crate gfx_device_gl;
-extern crate gfx_voxel;
-extern crate draw_state;
+extern crate hematite;
 extern crate image;
 extern crate input;
 extern crate quack;
@@ -20,74 +16,63 @@ extern crate time;
 extern crate vecmath;
 extern crate window;
 
-extern crate "rustc-serialize" as serialize;
-
-// Reexport modules from gfx_voxel while stuff is moving
-// from Hematite to the library.
-pub use gfx_voxel::{ array, cube };
-
 use std::cell::RefCell;
-use std::cmp::max;
+use std::collections::HashSet;
 use std::f32::consts::PI;
 use std::f32::INFINITY;
-use std::old_io::fs::File;
+use std::mem;
 use std::num::Float;
+use std::sync::mpsc::channel;
+use std::thread;
 
-use array::*;
 use event::{ Event, Events, MaxFps, Ups };
+use hematite::array::*;
+use hematite::{ chunk, minecraft };
+use hematite::shader::Renderer;
 use quack::{Get, Set};
 use sdl2_window::Sdl2Window;
-use shader::Renderer;
 use vecmath::{ vec3_add, vec3_scale, vec3_normalized };
 use window::{ CaptureCursor, Size, WindowSettings };
 
-use minecraft::biome::Biomes;
-use minecraft::block_state::BlockStates;
-
-pub mod chunk;
-pub mod shader;
+use hematite::minecraft::biome::Biomes;
+use hematite::minecraft::block_state::BlockStates;
+use hematite::minecraft::region::RegionError::MissingChunkError;
+use hematite::minecraft::world::{ Dimension, World };
 
-pub mod minecraft {
-    pub use self::data_1_8_pre2 as data;
+use LoaderRequest::*;
 
-    mod data_1_8_pre2;
-    pub mod biome;
-    pub mod block_state;
-    pub mod model;
-    pub mod nbt;
-    pub mod region;
+/// What the thread reading chunk columns is asked to do, in order.
+enum LoaderRequest {
+    /// Read the column at these chunk coordinates.
+    LoadColumn(i32, i32),
+    /// Close the regions out of the eviction radius of the column the
+    /// player is in.
+    EvictRegions(i32, i32)
 }
 
 fn main() {
     let mut args = std::env::args();
-    let world = args.nth(1).expect("Usage: ./hematite <path/to/world>");
-    let world = Path::new(&world);
-
-    let level_gzip = File::open(&world.join("level.dat"))
-        .read_to_end().unwrap();
-    let level = minecraft::nbt::Nbt::from_gzip(level_gzip.as_slice())
-        .unwrap();
-    println!("{:?}", level);
-    let player_pos: [f32; 3] = Array::from_iter(
-            level["Data"]["Player"]["Pos"]
-            .as_double_list().unwrap().iter().map(|&x| x as f32)
-        );
-    let player_chunk = [player_pos.x(), player_pos.z()]
-        .map(|x| (x / 16.0).floor() as i32);
-    let player_rot = level["Data"]["Player"]["Rotation"]
-        .as_float_list().unwrap();
-    let player_yaw = player_rot[0];
-    let player_pitch = player_rot[1];
-
-    let [region_x, region_z] = player_chunk.map(|x| x >> 5);
-    let region_file = world.join(
-            format!("region/r.{}.{}.mca", region_x, region_z)
-        );
-    let region = minecraft::region::Region::open(&region_file).unwrap();
-
+    let usage = "Usage: ./hematite <path/to/world> [view radius in chunks]";
+    let world_path = args.nth(1).expect(usage);
+    let world_path = Path::new(&world_path);
+    let radius: i32 = args.next().map(|r| r.parse().ok().expect(usage)).unwrap_or(8);
+
+    let world = World::open(&world_path).unwrap();
+    println!("{:?}", world.level());
+    let (dimension, player_pos, player_yaw, player_pitch) = match world.level().player {
+        Some(ref player) => (
+            player.dimension,
+            player.pos.map(|x| x as f32),
+            player.rotation[0],
+            player.rotation[1]
+        ),
+        // Servers keep players in their own files, so there might
+        // be no Player in level.dat; start at the spawn point then.
+        None => (Dimension::Overworld, world.level().spawn.map(|x| x as f32), 0.0, 0.0)
+    };
     let loading_title = format!(
             "Hematite loading... - {}",
-            world.filename_display()
+            world_path.filename_display()
         );
     let window = Sdl2Window::new(
         shader_version::OpenGL::_3_3,
@@ -117,23 +102,38 @@ fn main() {
 
     let mut chunk_manager = chunk::ChunkManager::new();
 
-    println!("Started loading chunks...");
-    let [cx_base, cz_base] = player_chunk.map(|x| max(0, (x & 0x1f) - 8) as u8);
-    for cz in range(cz_base, cz_base + 16) {
-        for cx in range(cx_base, cx_base + 16) {
-            match region.get_chunk_column(cx, cz) {
-                Some(column) => {
-                    let [cx, cz] = [
-                        cx as i32 + region_x * 32,
-                        cz as i32 + region_z * 32
-                    ];
-                    chunk_manager.add_chunk_column(cx, cz, column)
+    // Chunk columns are read on another thread, which opens the world
+    // again, as regions can't be shared between threads.
+    let (request_tx, request_rx) = channel();
+    let (column_tx, column_rx) = channel();
+    let loader_path = world_path.clone();
+    // Columns are only evicted a bit further out, so that moving back and
+    // forth over the edge of the view doesn't keep loading them again.
+    let evict_radius = radius + 2;
+    thread::spawn(move || {
+        let world = World::open(&loader_path).unwrap();
+        for request in request_rx.iter() {
+            match request {
+                LoadColumn(cx, cz) => {
+                    let blocks = world.chunk_blocks(dimension, cx, cz);
+                    if column_tx.send((cx, cz, blocks)).is_err() {
+                        break;
+                    }
+                }
+                EvictRegions(px, pz) => {
+                    let (x0, x1) = ((px - evict_radius) >> 5, (px + evict_radius) >> 5);
+                    let (z0, z1) = ((pz - evict_radius) >> 5, (pz + evict_radius) >> 5);
+                    world.evict_regions(dimension, |region_x, region_z| {
+                        x0 <= region_x && region_x <= x1 && z0 <= region_z && region_z <= z1
+                    });
                 }
-                None => {}
             }
         }
-    }
-    println!("Finished loading chunks.");
+    });
+    // Columns in view that were requested, including those not generated.
+    let mut requested = HashSet::new();
+    // The column the player was in when they were last requested.
+    let mut player_column = None;
 
     let projection_mat = camera_controllers::CameraPerspective {
         fov: 70.0,
@@ -161,12 +161,7 @@ fn main() {
 
     let mut fps_counter = fps_counter::FPSCounter::new();
 
-    let mut pending_chunks = vec![];
-    chunk_manager.each_chunk_and_neighbors(
-        |coords, buffer, chunks, column_biomes| {
-            pending_chunks.push((coords, buffer, chunks, column_biomes));
-        }
-    );
+    let mut pending_chunks = HashSet::new();
 
     let mut capture_cursor = false;
     println!("Press C to capture mouse");
@@ -256,43 +251,115 @@ fn main() {
                         num_total_chunks,
                         (end_time - start_time) as f64 / 1e6,
                         (frame_end_time - end_time) as f64 / 1e6,
-                        fps, world.filename_display()
+                        fps, world_path.filename_display()
                     );
                 window.borrow_mut().window.set_title(title.as_slice()).unwrap();
             }
             Event::Update(_) => {
-                // HACK(eddyb) find the closest chunk to the player.
-                // The pending vector should be sorted instead.
-                let closest = pending_chunks.iter().enumerate().min_by(
-                    |&(_, &([cx, cy, cz], _, _, _))| {
-                        let [px, py, pz] = first_person.position.map(|x|
-                            (x / 16.0).floor() as i32);
-                        let [x2, y2, z2] = [cx - px, cy - py, cz - pz]
-                            .map(|x| x * x);
-                        x2 + y2 + z2
+                let [px, pz] = [first_person.position[0], first_person.position[2]]
+                    .map(|x| (x / 16.0).floor() as i32);
+                let distance2 = |cx: i32, cz: i32| (cx - px) * (cx - px) + (cz - pz) * (cz - pz);
+
+                // What's in view only changes when the player moves to
+                // another column.
+                if player_column != Some((px, pz)) {
+                    player_column = Some((px, pz));
+
+                    // Request the columns that came into view, nearest first.
+                    let mut entering = vec![];
+                    for cz in range(pz - radius, pz + radius + 1) {
+                        for cx in range(px - radius, px + radius + 1) {
+                            if distance2(cx, cz) <= radius * radius
+                            && !requested.contains(&(cx, cz)) {
+                                entering.push((distance2(cx, cz), cx, cz));
+                            }
+                        }
                     }
-                ).map(|(i, _)| i);
-
-                let pending = closest.and_then(|i| {
-                    // Vec swap_remove doesn't return Option anymore
-                    match pending_chunks.len() {
-                        0 => None,
-                        _ => Some(pending_chunks.swap_remove(i))
+                    entering.sort();
+                    for &(_, cx, cz) in entering.iter() {
+                        requested.insert((cx, cz));
+                        request_tx.send(LoadColumn(cx, cz)).unwrap();
                     }
-                });
-                match pending {
-                    Some((coords, buffer, chunks, column_biomes)) => {
-                        minecraft::block_state::fill_buffer(
-                            &block_states, &biomes, &mut staging_buffer,
-                            coords, chunks, column_biomes
-                        );
-                        *buffer.borrow_mut() = Some(
-                            renderer.create_buffer(staging_buffer.as_slice())
+
+                    // Evict the columns that left the view, with their buffers,
+                    // and fill their neighbours' buffers again, for the seams.
+                    let leaving: Vec<(i32, i32)> = requested.iter()
+                        .filter(|&&(cx, cz)| distance2(cx, cz) > evict_radius * evict_radius)
+                        .map(|&coords| coords)
+                        .collect();
+                    for &(cx, cz) in leaving.iter() {
+                        requested.remove(&(cx, cz));
+                        // Columns that weren't read yet, or weren't generated,
+                        // have nothing to remove.
+                        let column = match chunk_manager.remove_chunk_column(cx, cz) {
+                            Some(column) => column,
+                            None => continue
+                        };
+                        for buffer in column.buffers.iter() {
+                            match buffer.borrow_mut().take() {
+                                Some(buffer) => renderer.delete_buffer(buffer),
+                                None => {}
+                            }
+                        }
+                        for y in range(0, column.chunks.len()) {
+                            pending_chunks.remove(&[cx, y as i32, cz]);
+                        }
+                        pending_chunks.extend(
+                            chunk_manager.chunks_around_column(cx, cz).into_iter()
                         );
-                        staging_buffer.clear();
+                    }
+                    // The loader can close the regions they were read from.
+                    if !leaving.is_empty() {
+                        request_tx.send(EvictRegions(px, pz)).unwrap();
+                    }
+                }
 
-                        if pending_chunks.is_empty() {
-                            println!("Finished filling chunk vertex buffers.");
+                // Add the columns that were read, unless they left the view
+                // in the meantime, or were requested twice.
+                while let Ok((cx, cz, blocks)) = column_rx.try_recv() {
+                    if !requested.contains(&(cx, cz)) || chunk_manager.has_chunk_column(cx, cz) {
+                        continue;
+                    }
+                    match blocks {
+                        Ok((chunks, column_biomes)) => {
+                            chunk_manager.add_chunk_column(
+                                cx, cz, chunk::ChunkColumn::new(chunks, column_biomes)
+                            );
+                            pending_chunks.extend(
+                                chunk_manager.chunks_around_column(cx, cz).into_iter()
+                            );
+                        }
+                        Err(MissingChunkError) => {}
+                        Err(e) => println!("Skipping chunk {}, {}: {}", cx, cz, e)
+                    }
+                }
+
+                // HACK(eddyb) find the closest chunk to the player.
+                // The pending set should be sorted instead.
+                let closest = pending_chunks.iter().min_by(|&&[cx, cy, cz]| {
+                    let py = (first_person.position[1] / 16.0).floor() as i32;
+                    distance2(cx, cz) + (cy - py) * (cy - py)
+                }).map(|&coords| coords);
+
+                match closest {
+                    Some(coords) => {
+                        pending_chunks.remove(&coords);
+                        match chunk_manager.chunk_and_neighbors(coords) {
+                            Some((buffer, chunks, column_biomes)) => {
+                                minecraft::block_state::fill_buffer(
+                                    &block_states, &biomes, &mut staging_buffer,
+                                    coords, chunks, column_biomes
+                                );
+                                let old = mem::replace(&mut *buffer.borrow_mut(), Some(
+                                    renderer.create_buffer(staging_buffer.as_slice())
+                                ));
+                                match old {
+                                    Some(old) => renderer.delete_buffer(old),
+                                    None => {}
+                                }
+                                staging_buffer.clear();
+                            }
+                            None => {}
                         }
                     }
                     None => {}
@@ -0,0 +1,331 @@
+//! LZ4 compression, framed the way Java's `LZ4BlockOutputStream` writes
+//! it, which is what region files use for compression type 4.
+
+use std::cmp::min;
+
+macro_rules! try_opt(
+    ($e:expr) => (match $e { Some(x) => x, None => return None })
+);
+
+const MAGIC: &'static [u8] = b"LZ4Block";
+const HEADER_SIZE: usize = 21;
+const BLOCK_SIZE: usize = 1 << 16;
+/// `LZ4BlockOutputStream`'s compression level for its block size.
+const BLOCK_LEVEL: u8 = 6;
+const METHOD_RAW: u8 = 0x10;
+const METHOD_LZ4: u8 = 0x20;
+const CHECKSUM_SEED: u32 = 0x9747b28c;
+
+const MIN_MATCH: usize = 4;
+/// The last match has to start this far from the end of a block.
+const MATCH_LIMIT: usize = 12;
+/// And the last bytes of a block are always literals.
+const LAST_LITERALS: usize = 5;
+const HASH_BITS: usize = 12;
+
+fn le_u32(b: &[u8]) -> u32 {
+    (b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+}
+
+fn push_le_u32(out: &mut Vec<u8>, x: u32) {
+    for i in range(0, 4) {
+        out.push((x >> (i * 8)) as u8);
+    }
+}
+
+// Arithmetic modulo 2^32, as xxHash needs.
+fn add(a: u32, b: u32) -> u32 { (a as u64 + b as u64) as u32 }
+fn mul(a: u32, b: u32) -> u32 { (a as u64 * b as u64) as u32 }
+fn rotl(x: u32, r: usize) -> u32 { (x << r) | (x >> (32 - r)) }
+
+const PRIME1: u32 = 2654435761;
+const PRIME2: u32 = 2246822519;
+const PRIME3: u32 = 3266489917;
+const PRIME4: u32 = 668265263;
+const PRIME5: u32 = 374761393;
+
+/// The 32-bit xxHash of `data`.
+pub fn xxhash32(data: &[u8], seed: u32) -> u32 {
+    let round = |v: u32, lane: &[u8]| mul(rotl(add(v, mul(le_u32(lane), PRIME2)), 13), PRIME1);
+    let mut i = 0;
+    let mut h = if data.len() >= 16 {
+        let mut v = [
+            add(add(seed, PRIME1), PRIME2),
+            add(seed, PRIME2),
+            seed,
+            add(seed, (-(PRIME1 as i64)) as u32)
+        ];
+        while i + 16 <= data.len() {
+            for j in range(0, 4) {
+                v[j] = round(v[j], &data[i + j * 4..]);
+            }
+            i += 16;
+        }
+        add(add(rotl(v[0], 1), rotl(v[1], 7)), add(rotl(v[2], 12), rotl(v[3], 18)))
+    } else {
+        add(seed, PRIME5)
+    };
+    h = add(h, data.len() as u32);
+    while i + 4 <= data.len() {
+        h = mul(rotl(add(h, mul(le_u32(&data[i..]), PRIME3)), 17), PRIME4);
+        i += 4;
+    }
+    for &b in data[i..].iter() {
+        h = mul(rotl(add(h, mul(b as u32, PRIME5)), 11), PRIME1);
+    }
+    h ^= h >> 15;
+    h = mul(h, PRIME2);
+    h ^= h >> 13;
+    h = mul(h, PRIME3);
+    h ^ (h >> 16)
+}
+
+/// The checksum `LZ4BlockOutputStream` stores for each block.
+fn checksum(data: &[u8]) -> u32 {
+    xxhash32(data, CHECKSUM_SEED) & 0x0fffffff
+}
+
+/// Reads a length continued in extra bytes, as long as they're 255.
+fn read_len(src: &[u8], i: &mut usize, mut len: usize) -> Option<usize> {
+    loop {
+        let b = match src.get(*i) { Some(&b) => b, None => return None };
+        *i += 1;
+        len += b as usize;
+        if b != 255 { return Some(len); }
+    }
+}
+
+/// Decompresses an LZ4 block into `out`, which has to end up `len` long.
+fn decompress_block(src: &[u8], len: usize, out: &mut Vec<u8>) -> Option<()> {
+    let end = out.len() + len;
+    let mut i = 0;
+    while i < src.len() {
+        let token = src[i];
+        i += 1;
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals = try_opt!(read_len(src, &mut i, literals));
+        }
+        if i + literals > src.len() || out.len() + literals > end {
+            return None;
+        }
+        out.push_all(&src[i..i + literals]);
+        i += literals;
+        // The last sequence has no match.
+        if i == src.len() { break; }
+
+        if i + 2 > src.len() { return None; }
+        let offset = src[i] as usize | (src[i + 1] as usize) << 8;
+        i += 2;
+        let mut match_len = (token & 15) as usize;
+        if match_len == 15 {
+            match_len = try_opt!(read_len(src, &mut i, match_len));
+        }
+        match_len += MIN_MATCH;
+        if offset == 0 || offset > out.len() || out.len() + match_len > end {
+            return None;
+        }
+        // Matches can overlap what they write, so copy byte by byte.
+        let start = out.len() - offset;
+        for j in range(start, start + match_len) {
+            let b = out[j];
+            out.push(b);
+        }
+    }
+    if out.len() == end { Some(()) } else { None }
+}
+
+fn push_len(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Writes literals, followed by a match at `offset` back of `len` bytes.
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((min(literals.len(), 15) << 4 | min(match_len, 15)) as u8);
+    if literals.len() >= 15 {
+        push_len(out, literals.len() - 15);
+    }
+    out.push_all(literals);
+    match matched {
+        Some((offset, _)) => {
+            out.push(offset as u8);
+            out.push((offset >> 8) as u8);
+            if match_len >= 15 {
+                push_len(out, match_len - 15);
+            }
+        }
+        None => {}
+    }
+}
+
+/// Compresses a block with greedy matching over a table of the last
+/// position each 4 byte sequence was seen at.
+fn compress_block(src: &[u8], out: &mut Vec<u8>) {
+    let mut table = [0usize; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+    while i + MATCH_LIMIT < src.len() {
+        let seq = le_u32(&src[i..]);
+        let h = ((seq as u64 * PRIME1 as u64) as u32 >> (32 - HASH_BITS)) as usize;
+        // Positions are stored one up, so that 0 is empty.
+        let candidate = table[h];
+        table[h] = i + 1;
+        if candidate > 0 && i + 1 - candidate <= 0xffff
+           && le_u32(&src[candidate - 1..]) == seq {
+            let from = candidate - 1;
+            let max_len = src.len() - LAST_LITERALS - i;
+            let mut len = MIN_MATCH;
+            while len < max_len && src[from + len] == src[i + len] {
+                len += 1;
+            }
+            push_sequence(out, &src[anchor..i], Some((i - from, len)));
+            i += len;
+            anchor = i;
+        } else {
+            i += 1;
+        }
+    }
+    push_sequence(out, &src[anchor..], None);
+}
+
+fn push_header(out: &mut Vec<u8>, method: u8, compressed_len: usize, len: usize, checksum: u32) {
+    out.push_all(MAGIC);
+    out.push(method | BLOCK_LEVEL);
+    push_le_u32(out, compressed_len as u32);
+    push_le_u32(out, len as u32);
+    push_le_u32(out, checksum);
+}
+
+/// Compresses data into LZ4 blocks of 64 KiB, ending with an empty block.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut block = Vec::new();
+    for chunk in data.chunks(BLOCK_SIZE) {
+        block.clear();
+        compress_block(chunk, &mut block);
+        // Data that doesn't compress is stored as it is.
+        if block.len() < chunk.len() {
+            push_header(&mut out, METHOD_LZ4, block.len(), chunk.len(), checksum(chunk));
+            out.push_all(block.as_slice());
+        } else {
+            push_header(&mut out, METHOD_RAW, chunk.len(), chunk.len(), checksum(chunk));
+            out.push_all(chunk);
+        }
+    }
+    push_header(&mut out, METHOD_RAW, 0, 0, 0);
+    out
+}
+
+/// Decompresses a stream of LZ4 blocks, checking their checksums, as
+/// long as they add up to no more than `max_len` bytes.
+pub fn decompress(data: &[u8], max_len: u64) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        if data.len() < i + HEADER_SIZE || &data[i..i + MAGIC.len()] != MAGIC {
+            return None;
+        }
+        let header = &data[i + MAGIC.len()..i + HEADER_SIZE];
+        let compressed_len = le_u32(&header[1..]) as usize;
+        let len = le_u32(&header[5..]) as usize;
+        i += HEADER_SIZE;
+        if data.len() - i < compressed_len || (out.len() + len) as u64 > max_len {
+            return None;
+        }
+        let block = &data[i..i + compressed_len];
+        i += compressed_len;
+
+        let start = out.len();
+        match header[0] & 0xf0 {
+            METHOD_RAW if compressed_len == len => out.push_all(block),
+            METHOD_LZ4 => try_opt!(decompress_block(block, len, &mut out)),
+            _ => return None
+        }
+        if len > 0 && checksum(&out[start..]) != le_u32(&header[9..]) {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::repeat;
+    use std::u64;
+
+    use super::{ compress, decompress, xxhash32, BLOCK_SIZE };
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data);
+        assert_eq!(decompress(compressed.as_slice(), u64::MAX).unwrap().as_slice(), data);
+    }
+
+    /// Bytes that don't compress, from a linear congruential generator.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x = 1u32;
+        range(0, len).map(|_| {
+            x = (x as u64 * 1103515245 + 12345) as u32;
+            (x >> 24) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn xxhash32_vectors() {
+        assert_eq!(xxhash32(b"", 0), 0x02cc5d05);
+        assert_eq!(xxhash32(b"abc", 0), 0x32d153ff);
+        assert_eq!(xxhash32(b"Nobody inspects the spammish repetition", 0), 0xe2293b2f);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"");
+        round_trip(b"hello");
+        round_trip(noise(1000).as_slice());
+        let zeros: Vec<u8> = repeat(0).take(5000).collect();
+        round_trip(zeros.as_slice());
+        // Several blocks, the last of them partial.
+        let mut mixed = noise(BLOCK_SIZE);
+        mixed.extend(repeat(7).take(BLOCK_SIZE * 2 + 123));
+        mixed.push_all(noise(999).as_slice());
+        round_trip(mixed.as_slice());
+    }
+
+    #[test]
+    fn compresses_repetitive_data() {
+        let zeros: Vec<u8> = repeat(0).take(BLOCK_SIZE).collect();
+        assert!(compress(zeros.as_slice()).len() < 1024);
+        // An empty stream is only the closing block.
+        assert_eq!(compress(b"").len(), 21);
+    }
+
+    /// Streams laid out the way `LZ4BlockOutputStream` writes them with
+    /// its default 64 KiB blocks: the magic, method and level token, the
+    /// lengths, the masked xxHash of the block, and an empty last block.
+    #[test]
+    fn java_streams() {
+        let end = b"LZ4Block\x16\0\0\0\0\0\0\0\0\0\0\0\0";
+
+        // Too short to compress, so stored raw.
+        let mut raw = b"LZ4Block\x16\x05\0\0\0\x05\0\0\0\xe3\xbf\x41\x0ahello".to_vec();
+        raw.push_all(end);
+        assert_eq!(decompress(raw.as_slice(), u64::MAX).unwrap(), b"hello".to_vec());
+
+        // 64 'a's: one literal, a match of 58 and the last 5 literals.
+        let mut lz4 = b"LZ4Block\x26\x0b\0\0\0\x40\0\0\0\x23\xf0\xf6\x0f".to_vec();
+        lz4.push_all(b"\x1fa\x01\x00\x27\x50aaaaa");
+        lz4.push_all(end);
+        let a: Vec<u8> = repeat(b'a').take(64).collect();
+        assert_eq!(decompress(lz4.as_slice(), u64::MAX).unwrap(), a);
+        assert_eq!(decompress(lz4.as_slice(), 63), None);
+
+        // A corrupt checksum is caught.
+        let mut corrupt = raw.clone();
+        corrupt[17] ^= 1;
+        assert_eq!(decompress(corrupt.as_slice(), u64::MAX), None);
+    }
+}
@@ -0,0 +1,465 @@
+//! A NBT view borrowing straight from the uncompressed bytes, so that
+//! arrays and strings can be used without copying them out.
+
+use std::borrow::Cow;
+use std::mem;
+use std::num::Int;
+
+use super::{ decode_mutf8, encode_mutf8, Compound, List, Nbt, NbtLimits, NbtPos, NbtResult };
+use super::NbtError::*;
+use super::{ TAG_END, TAG_BYTE, TAG_SHORT, TAG_INT, TAG_LONG, TAG_FLOAT, TAG_DOUBLE,
+             TAG_BYTE_ARRAY, TAG_STRING, TAG_LIST, TAG_COMPOUND, TAG_INT_ARRAY,
+             TAG_LONG_ARRAY };
+
+use self::NbtRef::*;
+
+/// A NBT value borrowed from a byte slice, see `NbtRef::from_slice`.
+#[derive(Copy, Clone, Debug)]
+pub enum NbtRef<'a> {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(&'a [u8]),
+    IntArray(IntArrayRef<'a>),
+    LongArray(LongArrayRef<'a>),
+    NbtString(StrRef<'a>),
+    NbtList(ListRef<'a>),
+    NbtCompound(CompoundRef<'a>)
+}
+
+fn be_u16(d: &[u8]) -> u16 {
+    (d[0] as u16) << 8 | d[1] as u16
+}
+
+fn be_u32(d: &[u8]) -> u32 {
+    (d[0] as u32) << 24 | (d[1] as u32) << 16 | (d[2] as u32) << 8 | d[3] as u32
+}
+
+fn be_u64(d: &[u8]) -> u64 {
+    (be_u32(d) as u64) << 32 | be_u32(&d[4..]) as u64
+}
+
+fn be_i32(d: &[u8]) -> i32 { be_u32(d) as i32 }
+fn be_i64(d: &[u8]) -> i64 { be_u64(d) as i64 }
+
+macro_rules! be_array(
+    ($name:ident, $iter:ident, $t:ty, $size:expr, $read:expr) => (
+        /// A borrowed array of big-endian numbers.
+        #[derive(Copy, Clone, Debug)]
+        pub struct $name<'a> {
+            data: &'a [u8]
+        }
+
+        impl<'a> $name<'a> {
+            pub fn len(&self) -> usize {
+                self.data.len() / $size
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.data.is_empty()
+            }
+
+            pub fn get(&self, i: usize) -> Option<$t> {
+                if i < self.len() {
+                    Some($read(&self.data[i * $size..]))
+                } else {
+                    None
+                }
+            }
+
+            pub fn iter(&self) -> $iter<'a> {
+                $iter { data: self.data }
+            }
+
+            pub fn to_vec(&self) -> Vec<$t> {
+                self.iter().collect()
+            }
+        }
+
+        pub struct $iter<'a> {
+            data: &'a [u8]
+        }
+
+        impl<'a> Iterator for $iter<'a> {
+            type Item = $t;
+
+            fn next(&mut self) -> Option<$t> {
+                if self.data.is_empty() {
+                    return None;
+                }
+                let x = $read(self.data);
+                self.data = &self.data[$size..];
+                Some(x)
+            }
+        }
+    )
+);
+
+be_array!(IntArrayRef, IntArrayIter, i32, 4, be_i32);
+be_array!(LongArrayRef, LongArrayIter, i64, 8, be_i64);
+
+/// A borrowed string, still encoded as modified UTF-8.
+#[derive(Copy, Clone, Debug)]
+pub struct StrRef<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> StrRef<'a> {
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Decodes the string, only allocating if it isn't plain UTF-8.
+    pub fn to_str(&self) -> Option<Cow<'a, str>> {
+        match ::std::str::from_utf8(self.data) {
+            Ok(s) => Some(Cow::Borrowed(s)),
+            Err(_) => decode_mutf8(self.data).map(Cow::Owned)
+        }
+    }
+}
+
+/// A borrowed list, whose elements are decoded while iterating.
+#[derive(Copy, Clone, Debug)]
+pub struct ListRef<'a> {
+    tag_type: i8,
+    len: usize,
+    data: &'a [u8]
+}
+
+impl<'a> ListRef<'a> {
+    /// The tag type of the elements in this list.
+    pub fn tag_type(&self) -> i8 {
+        self.tag_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Indexes straight into lists of numbers, and skips over the
+    /// elements before `i` in others.
+    pub fn get(&self, i: usize) -> Option<NbtRef<'a>> {
+        if i >= self.len {
+            return None;
+        }
+        let start = match fixed_len(self.tag_type) {
+            Some(len) => i * len,
+            None => range(0, i).fold(0, |pos, _| {
+                pos + skip_len(&self.data[pos..], self.tag_type)
+            })
+        };
+        Some(payload(&self.data[start..], self.tag_type).0)
+    }
+
+    pub fn iter(&self) -> ListIter<'a> {
+        ListIter {
+            tag_type: self.tag_type,
+            remaining: self.len,
+            data: self.data
+        }
+    }
+}
+
+pub struct ListIter<'a> {
+    tag_type: i8,
+    remaining: usize,
+    data: &'a [u8]
+}
+
+impl<'a> Iterator for ListIter<'a> {
+    type Item = NbtRef<'a>;
+
+    fn next(&mut self) -> Option<NbtRef<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let (value, len) = payload(self.data, self.tag_type);
+        self.data = &self.data[len..];
+        Some(value)
+    }
+}
+
+/// A borrowed compound, whose entries are decoded while iterating.
+#[derive(Copy, Clone, Debug)]
+pub struct CompoundRef<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> CompoundRef<'a> {
+    /// Looks up an entry by name, scanning the compound.
+    pub fn get(&self, name: &str) -> Option<NbtRef<'a>> {
+        let name = encode_mutf8(name);
+        self.iter().find(|&(key, _)| key.as_bytes() == &*name).map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> CompoundIter<'a> {
+        CompoundIter { data: self.data }
+    }
+}
+
+pub struct CompoundIter<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> Iterator for CompoundIter<'a> {
+    type Item = (StrRef<'a>, NbtRef<'a>);
+
+    fn next(&mut self) -> Option<(StrRef<'a>, NbtRef<'a>)> {
+        let tag_type = self.data[0] as i8;
+        if tag_type == TAG_END {
+            return None;
+        }
+        let name_len = be_u16(&self.data[1..]) as usize;
+        let name = StrRef { data: &self.data[3..3 + name_len] };
+        let (value, len) = payload(&self.data[3 + name_len..], tag_type);
+        self.data = &self.data[3 + name_len + len..];
+        Some((name, value))
+    }
+}
+
+/// The length of payloads that don't depend on their contents.
+fn fixed_len(tag_type: i8) -> Option<usize> {
+    match tag_type {
+        TAG_BYTE => Some(1),
+        TAG_SHORT => Some(2),
+        TAG_INT | TAG_FLOAT => Some(4),
+        TAG_LONG | TAG_DOUBLE => Some(8),
+        _ => None
+    }
+}
+
+/// The length of the payload at the start of already validated `data`,
+/// found without checking it again.
+fn skip_len(data: &[u8], tag_type: i8) -> usize {
+    match fixed_len(tag_type) {
+        Some(len) => return len,
+        None => {}
+    }
+    match tag_type {
+        TAG_BYTE_ARRAY => 4 + be_u32(data) as usize,
+        TAG_INT_ARRAY => 4 + be_u32(data) as usize * 4,
+        TAG_LONG_ARRAY => 4 + be_u32(data) as usize * 8,
+        TAG_STRING => 2 + be_u16(data) as usize,
+        TAG_LIST => {
+            let element_type = data[0] as i8;
+            let len = be_u32(&data[1..]) as usize;
+            match fixed_len(element_type) {
+                Some(element_len) => 5 + len * element_len,
+                None => range(0, len).fold(5, |pos, _| {
+                    pos + skip_len(&data[pos..], element_type)
+                })
+            }
+        }
+        _ => {
+            let mut pos = 0;
+            loop {
+                let tag_type = data[pos] as i8;
+                pos += 1;
+                if tag_type == TAG_END {
+                    return pos;
+                }
+                pos += 2 + be_u16(&data[pos..]) as usize;
+                pos += skip_len(&data[pos..], tag_type);
+            }
+        }
+    }
+}
+
+/// Decodes the payload at the start of already validated `data`,
+/// returning it along with its length in bytes.
+fn payload<'a>(data: &'a [u8], tag_type: i8) -> (NbtRef<'a>, usize) {
+    let len = skip_len(data, tag_type);
+    let value = match tag_type {
+        TAG_BYTE => Byte(data[0] as i8),
+        TAG_SHORT => Short(be_u16(data) as i16),
+        TAG_INT => Int(be_u32(data) as i32),
+        TAG_LONG => Long(be_u64(data) as i64),
+        TAG_FLOAT => Float(unsafe { mem::transmute(be_u32(data)) }),
+        TAG_DOUBLE => Double(unsafe { mem::transmute(be_u64(data)) }),
+        TAG_BYTE_ARRAY => ByteArray(&data[4..len]),
+        TAG_INT_ARRAY => IntArray(IntArrayRef { data: &data[4..len] }),
+        TAG_LONG_ARRAY => LongArray(LongArrayRef { data: &data[4..len] }),
+        TAG_STRING => NbtString(StrRef { data: &data[2..len] }),
+        TAG_LIST => NbtList(ListRef {
+            tag_type: data[0] as i8,
+            len: be_u32(&data[1..]) as usize,
+            data: &data[5..len]
+        }),
+        _ => NbtCompound(CompoundRef { data: &data[..len] })
+    };
+    (value, len)
+}
+
+/// Checks that `prefix` bytes followed by `count` elements of `size`
+/// bytes fit at `data[pos..]`, returning their length.
+fn prefixed_len(data: &[u8], pos: usize, prefix: usize, count: usize, size: usize)
+                -> NbtResult<usize> {
+    match count.checked_mul(size).and_then(|len| len.checked_add(prefix)) {
+        Some(len) if len <= data.len() - pos => Ok(len),
+        _ => Err(TruncatedError(NbtPos::at(data.len() as u64)))
+    }
+}
+
+fn check_tag_type(tag_type: i8, pos: usize) -> NbtResult<()> {
+    if tag_type < TAG_END || tag_type > TAG_LONG_ARRAY {
+        Err(UnknownTagError(tag_type, NbtPos::at(pos as u64)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Validates the payload of a tag starting at `data[pos..]`, nested at
+/// most `depth` levels deep, returning its length in bytes.
+fn payload_len(data: &[u8], pos: usize, tag_type: i8, depth: usize) -> NbtResult<usize> {
+    let count = || -> NbtResult<usize> {
+        try!(prefixed_len(data, pos, 4, 0, 0));
+        Ok(be_i32(&data[pos..]) as usize)
+    };
+    match tag_type {
+        TAG_BYTE => prefixed_len(data, pos, 1, 0, 0),
+        TAG_SHORT => prefixed_len(data, pos, 2, 0, 0),
+        TAG_INT | TAG_FLOAT => prefixed_len(data, pos, 4, 0, 0),
+        TAG_LONG | TAG_DOUBLE => prefixed_len(data, pos, 8, 0, 0),
+        TAG_BYTE_ARRAY => prefixed_len(data, pos, 4, try!(count()), 1),
+        TAG_INT_ARRAY => prefixed_len(data, pos, 4, try!(count()), 4),
+        TAG_LONG_ARRAY => prefixed_len(data, pos, 4, try!(count()), 8),
+        TAG_STRING => {
+            try!(prefixed_len(data, pos, 2, 0, 0));
+            prefixed_len(data, pos, 2, be_u16(&data[pos..]) as usize, 1)
+        }
+        TAG_LIST | TAG_COMPOUND if depth == 0 => {
+            Err(DepthLimitError(NbtPos::at(pos as u64)))
+        }
+        TAG_LIST => {
+            try!(prefixed_len(data, pos, 5, 0, 0));
+            let element_type = data[pos] as i8;
+            try!(check_tag_type(element_type, pos));
+            let len = be_i32(&data[pos + 1..]) as usize;
+            // Only empty lists may leave out the type of their elements.
+            if element_type == TAG_END && len != 0 {
+                return Err(UnknownTagError(TAG_END, NbtPos::at(pos as u64)));
+            }
+            let mut end = pos + 5;
+            for i in range(0, len) {
+                match payload_len(data, end, element_type, depth - 1) {
+                    Ok(n) => end += n,
+                    Err(e) => return Err(e.within(format!("[{}]", i)))
+                }
+            }
+            Ok(end - pos)
+        }
+        _ => {
+            let mut end = pos;
+            loop {
+                try!(prefixed_len(data, end, 1, 0, 0));
+                let tag_type = data[end] as i8;
+                try!(check_tag_type(tag_type, end));
+                end += 1;
+                if tag_type == TAG_END {
+                    return Ok(end - pos);
+                }
+                let name_len = try!(payload_len(data, end, TAG_STRING, 0));
+                let name_end = end + name_len;
+                match payload_len(data, name_end, tag_type, depth - 1) {
+                    Ok(n) => end = name_end + n,
+                    Err(e) => {
+                        let name = decode_mutf8(&data[end + 2..name_end])
+                            .unwrap_or(String::new());
+                        return Err(e.within(name));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> NbtRef<'a> {
+    /// Validates the uncompressed NBT stream in `data` and returns a view
+    /// of its root tag. Nothing is copied or allocated. Only the `Java`
+    /// flavour can be viewed this way.
+    pub fn from_slice(data: &'a [u8]) -> NbtResult<NbtRef<'a>> {
+        NbtRef::from_slice_with_limits(data, NbtLimits::default())
+    }
+
+    /// Like `from_slice`, but with other bounds on nesting and size.
+    pub fn from_slice_with_limits(data: &'a [u8], limits: NbtLimits) -> NbtResult<NbtRef<'a>> {
+        if data.len() as u64 > limits.max_bytes {
+            return Err(SizeLimitError(NbtPos::at(limits.max_bytes)));
+        }
+        try!(prefixed_len(data, 0, 1, 0, 0));
+        let tag_type = data[0] as i8;
+        if tag_type == TAG_END {
+            return Err(UnknownTagError(TAG_END, NbtPos::at(0)));
+        }
+        try!(check_tag_type(tag_type, 0));
+        let name_len = try!(payload_len(data, 1, TAG_STRING, 0));
+        try!(payload_len(data, 1 + name_len, tag_type, limits.max_depth));
+        Ok(payload(&data[1 + name_len..], tag_type).0)
+    }
+
+    pub fn as_byte(&self) -> Option<i8> {
+        match *self { Byte(b) => Some(b), _ => None }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&'a [u8]> {
+        match *self { ByteArray(b) => Some(b), _ => None }
+    }
+
+    #[deprecated(since = "0.0.0", reason = "renamed to `as_byte_array`")]
+    pub fn as_bytearray(&self) -> Option<&'a [u8]> {
+        self.as_byte_array()
+    }
+
+    pub fn as_list(&self) -> Option<ListRef<'a>> {
+        match *self { NbtList(l) => Some(l), _ => None }
+    }
+
+    pub fn as_compound(&self) -> Option<CompoundRef<'a>> {
+        match *self { NbtCompound(c) => Some(c), _ => None }
+    }
+
+    /// Looks up an entry of a compound, see `CompoundRef::get`.
+    pub fn get(&self, name: &str) -> Option<NbtRef<'a>> {
+        self.as_compound().and_then(|c| c.get(name))
+    }
+
+    /// Copies the value into an owned `Nbt` tree. Strings that are not
+    /// valid modified UTF-8 come out as U+FFFD.
+    pub fn to_nbt(&self) -> Nbt {
+        fn string(s: StrRef) -> String {
+            s.to_str().map(|s| s.into_owned()).unwrap_or("\u{fffd}".to_string())
+        }
+        fn list(l: ListRef) -> List {
+            let tags = l.iter().map(|x| x.to_nbt()).collect();
+            List::from_tags(tags).unwrap()
+        }
+        match *self {
+            Byte(x) => Nbt::Byte(x),
+            Short(x) => Nbt::Short(x),
+            Int(x) => Nbt::Int(x),
+            Long(x) => Nbt::Long(x),
+            Float(x) => Nbt::Float(x),
+            Double(x) => Nbt::Double(x),
+            ByteArray(x) => Nbt::ByteArray(x.to_vec()),
+            IntArray(x) => Nbt::IntArray(x.to_vec()),
+            LongArray(x) => Nbt::LongArray(x.to_vec()),
+            NbtString(s) => Nbt::NbtString(string(s)),
+            NbtList(l) => Nbt::NbtList(list(l)),
+            NbtCompound(c) => {
+                let mut compound = Compound::new();
+                for (k, v) in c.iter() {
+                    compound.insert(string(k), v.to_nbt());
+                }
+                Nbt::NbtCompound(compound)
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+use std::slice;
+use std::vec;
+
+use super::{ IntoNbt, Nbt };
+
+/// A list of named NBT values, kept in insertion order so that files
+/// are written back with their keys in the same order as read.
+///
+/// Keys are also indexed by a hash map, so that compounds from untrusted
+/// files with many keys don't take quadratic time to read.
+#[derive(Clone)]
+pub struct Compound {
+    entries: Vec<(String, Nbt)>,
+    /// The position of each key in `entries`.
+    index: HashMap<String, usize>
+}
+
+impl Compound {
+    pub fn new() -> Compound {
+        Compound {
+            entries: Vec::new(),
+            index: HashMap::new()
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Compound {
+        Compound {
+            entries: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.index.get(key).map(|&i| i)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Nbt> {
+        self.position(key).map(|i| &self.entries[i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Nbt> {
+        match self.position(key) {
+            Some(i) => Some(&mut self.entries[i].1),
+            None => None
+        }
+    }
+
+    /// Inserts a value, returning the previous one for `key`. A replaced
+    /// value keeps its position, new keys are appended.
+    pub fn insert(&mut self, key: String, value: Nbt) -> Option<Nbt> {
+        match self.position(key.as_slice()) {
+            Some(i) => Some(::std::mem::replace(&mut self.entries[i].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Adds an entry, for building compounds in one expression:
+    /// `Compound::new().with("id", "minecraft:stone").with("Count", 1i8)`.
+    pub fn with<V: IntoNbt>(mut self, key: &str, value: V) -> Compound {
+        self.insert(key.to_string(), value.into_nbt());
+        self
+    }
+
+    /// Removes a value, keeping the order of the remaining ones.
+    pub fn remove(&mut self, key: &str) -> Option<Nbt> {
+        let i = match self.index.remove(key) {
+            Some(i) => i,
+            None => return None
+        };
+        // The entries after it move down by one.
+        for &(ref k, _) in self.entries[i + 1..].iter() {
+            *self.index.get_mut(k.as_slice()).unwrap() -= 1;
+        }
+        Some(self.entries.remove(i).1)
+    }
+
+    pub fn iter(&self) -> Iter {
+        Iter { inner: self.entries.iter() }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut {
+        IterMut { inner: self.entries.iter_mut() }
+    }
+
+    pub fn into_iter(self) -> vec::IntoIter<(String, Nbt)> {
+        self.entries.into_iter()
+    }
+
+    pub fn keys(&self) -> Keys {
+        Keys { inner: self.entries.iter() }
+    }
+}
+
+/// Compounds are equal if they have the same entries, in any order.
+impl PartialEq for Compound {
+    fn eq(&self, other: &Compound) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| {
+            other.get(k.as_slice()) == Some(v)
+        })
+    }
+}
+
+impl fmt::Debug for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{{"));
+        for (i, (k, v)) in self.iter().enumerate() {
+            if i > 0 {
+                try!(write!(f, ", "));
+            }
+            try!(write!(f, "{:?}: {:?}", k, v));
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<'a> Index<&'a str> for Compound {
+    type Output = Nbt;
+
+    fn index<'b>(&'b self, s: &&'a str) -> &'b Nbt {
+        match self.get(*s) {
+            Some(v) => v,
+            None => panic!("no entry '{}' in compound", s)
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    inner: slice::Iter<'a, (String, Nbt)>
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a Nbt);
+
+    fn next(&mut self) -> Option<(&'a String, &'a Nbt)> {
+        self.inner.next().map(|&(ref k, ref v)| (k, v))
+    }
+}
+
+pub struct IterMut<'a> {
+    inner: slice::IterMut<'a, (String, Nbt)>
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (&'a String, &'a mut Nbt);
+
+    fn next(&mut self) -> Option<(&'a String, &'a mut Nbt)> {
+        self.inner.next().map(|&mut (ref k, ref mut v)| (k, v))
+    }
+}
+
+pub struct Keys<'a> {
+    inner: slice::Iter<'a, (String, Nbt)>
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = &'a String;
+
+    fn next(&mut self) -> Option<&'a String> {
+        self.inner.next().map(|&(ref k, _)| k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compound;
+    use super::super::Nbt::Int;
+
+    #[test]
+    fn remove_keeps_order_and_lookups() {
+        let mut c = Compound::new().with("a", 1i32).with("b", 2i32).with("c", 3i32);
+        assert_eq!(c.remove("b"), Some(Int(2)));
+        assert_eq!(c.remove("b"), None);
+        assert_eq!(c.get("a"), Some(&Int(1)));
+        assert_eq!(c.get("c"), Some(&Int(3)));
+        c.insert("b".to_string(), Int(4));
+        let keys: Vec<&str> = c.keys().map(|k| k.as_slice()).collect();
+        assert_eq!(keys, vec!["a", "c", "b"]);
+        assert_eq!(c.insert("c".to_string(), Int(5)), Some(Int(3)));
+        assert_eq!(c.get("c"), Some(&Int(5)));
+    }
+}
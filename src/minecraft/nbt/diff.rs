@@ -0,0 +1,303 @@
+//! Structural differences between NBT trees, and replaying them.
+
+use std::cmp::min;
+use std::error::Error;
+use std::fmt;
+
+use super::{ Compound, List, Nbt, NbtPath, TAG_END };
+use super::Nbt::*;
+use super::List::*;
+use super::PathNode::{ Index, Key };
+
+use self::Change::*;
+use self::PatchError::*;
+
+/// A difference between two trees, at the path of the tag that differs.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Change {
+    /// A compound entry or list element only found in the new tree.
+    Added(NbtPath, Nbt),
+    /// A compound entry or list element only found in the old tree.
+    Removed(NbtPath, Nbt),
+    /// A tag with different values in the old and new tree.
+    Changed(NbtPath, Nbt, Nbt)
+}
+
+impl Change {
+    pub fn path(&self) -> &NbtPath {
+        match *self {
+            Added(ref path, _) | Removed(ref path, _) | Changed(ref path, _, _) => path
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Added(ref path, ref new) => write!(f, "+ {}: {}", path, new.to_snbt()),
+            Removed(ref path, ref old) => write!(f, "- {}: {}", path, old.to_snbt()),
+            Changed(ref path, ref old, ref new) => {
+                write!(f, "~ {}: {} -> {}", path, old.to_snbt(), new.to_snbt())
+            }
+        }
+    }
+}
+
+/// An error replaying a change onto a tree that doesn't match its old one.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PatchError {
+    /// The tag to change or remove, or the parent to add to, is missing.
+    MissingError(NbtPath),
+    /// The tag to change or remove has another value, or the one to
+    /// add already exists.
+    ConflictError(NbtPath)
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MissingError(ref path) | ConflictError(ref path) => {
+                write!(f, "{} at '{}'", self.description(), path)
+            }
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn description(&self) -> &str {
+        match *self {
+            MissingError(..) => "missing tag",
+            ConflictError(..) => "conflicting tag"
+        }
+    }
+}
+
+/// Lists the changes from `old` to `new`. Compounds are compared by key and
+/// lists by index, while other tags, including arrays, are compared whole.
+pub fn diff(old: &Nbt, new: &Nbt) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_tag(&mut NbtPath::from_nodes(Vec::new()), old, new, &mut changes);
+    changes
+}
+
+fn diff_tag(path: &mut NbtPath, old: &Nbt, new: &Nbt, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (&NbtCompound(ref a), &NbtCompound(ref b)) => diff_compound(path, a, b, changes),
+        (&NbtList(ref a), &NbtList(ref b)) => diff_list(path, a, b, changes),
+        _ => {
+            if old != new {
+                changes.push(Changed(path.clone(), old.clone(), new.clone()));
+            }
+        }
+    }
+}
+
+fn diff_compound(path: &mut NbtPath, a: &Compound, b: &Compound, changes: &mut Vec<Change>) {
+    for (k, old) in a.iter() {
+        path.push(Key(k.clone()));
+        match b.get(k.as_slice()) {
+            Some(new) => diff_tag(path, old, new, changes),
+            None => changes.push(Removed(path.clone(), old.clone()))
+        }
+        path.pop();
+    }
+    for (k, new) in b.iter() {
+        if !a.contains_key(k.as_slice()) {
+            path.push(Key(k.clone()));
+            changes.push(Added(path.clone(), new.clone()));
+            path.pop();
+        }
+    }
+}
+
+fn diff_list(path: &mut NbtPath, a: &List, b: &List, changes: &mut Vec<Change>) {
+    // Elements can only be added one by one to the untyped empty list.
+    if a.tag_type() != b.tag_type() && !(a.tag_type() == TAG_END && !b.is_empty()) {
+        changes.push(Changed(path.clone(), NbtList(a.clone()), NbtList(b.clone())));
+        return;
+    }
+    let common = min(a.len(), b.len());
+    match (a, b) {
+        (&CompoundList(ref x), &CompoundList(ref y)) => {
+            for i in range(0, common) {
+                path.push(Index(i as i32));
+                diff_compound(path, &x[i], &y[i], changes);
+                path.pop();
+            }
+        }
+        (&ListList(ref x), &ListList(ref y)) => {
+            for i in range(0, common) {
+                path.push(Index(i as i32));
+                diff_list(path, &x[i], &y[i], changes);
+                path.pop();
+            }
+        }
+        _ => {
+            for i in range(0, common) {
+                let (old, new) = (a.get(i).unwrap(), b.get(i).unwrap());
+                if old != new {
+                    path.push(Index(i as i32));
+                    changes.push(Changed(path.clone(), old, new));
+                    path.pop();
+                }
+            }
+        }
+    }
+    // Removed from the back, so the indices stay valid when applied in order.
+    for i in range(common, a.len()).rev() {
+        path.push(Index(i as i32));
+        changes.push(Removed(path.clone(), a.get(i).unwrap()));
+        path.pop();
+    }
+    for i in range(common, b.len()) {
+        path.push(Index(i as i32));
+        changes.push(Added(path.clone(), b.get(i).unwrap()));
+        path.pop();
+    }
+}
+
+/// Replays the changes from `diff` onto a tree, in order. Every tag that is
+/// changed or removed has to have its old value, or nothing more is applied.
+pub fn apply(nbt: &mut Nbt, changes: &[Change]) -> Result<(), PatchError> {
+    for change in changes.iter() {
+        match *change {
+            Added(ref path, ref new) => try!(add(nbt, path, new)),
+            Removed(ref path, ref old) => {
+                try!(check(nbt, path, old));
+                path.remove(nbt);
+            }
+            Changed(ref path, ref old, ref new) => {
+                try!(check(nbt, path, old));
+                path.set(nbt, new.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check(nbt: &Nbt, path: &NbtPath, old: &Nbt) -> Result<(), PatchError> {
+    match path.get(nbt) {
+        Some(ref target) if target.to_nbt() == *old => Ok(()),
+        Some(_) => Err(ConflictError(path.clone())),
+        None => Err(MissingError(path.clone()))
+    }
+}
+
+fn add(nbt: &mut Nbt, path: &NbtPath, new: &Nbt) -> Result<(), PatchError> {
+    let nodes = path.nodes();
+    if nodes.is_empty() {
+        return Err(ConflictError(path.clone()));
+    }
+    let parent = NbtPath::from_nodes(nodes[..nodes.len() - 1].to_vec());
+    let added = match (parent.get_all_mut(nbt).into_iter().next(), &nodes[nodes.len() - 1]) {
+        (Some(target), &Key(ref k)) => match target.into_compound() {
+            Some(c) => {
+                if c.contains_key(k.as_slice()) {
+                    return Err(ConflictError(path.clone()));
+                }
+                c.insert(k.clone(), new.clone());
+                true
+            }
+            None => false
+        },
+        (Some(target), &Index(i)) => match target.into_list() {
+            Some(l) => i >= 0 && l.insert(i as usize, new.clone()).is_ok(),
+            None => false
+        },
+        _ => false
+    };
+    if added { Ok(()) } else { Err(MissingError(path.clone())) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Change::*;
+    use super::PatchError::*;
+    use super::super::{ Compound, List, Nbt, NbtPath };
+    use super::super::Nbt::*;
+    use super::super::List::*;
+
+    fn round_trip(old: Nbt, new: Nbt) -> Vec<Change> {
+        let changes = diff(&old, &new);
+        let mut patched = old.clone();
+        assert_eq!(apply(&mut patched, changes.as_slice()), Ok(()));
+        assert_eq!(patched, new);
+        changes
+    }
+
+    fn with(key: &str, value: Nbt) -> Nbt {
+        NbtCompound(Compound::new().with(key, value))
+    }
+
+    #[test]
+    fn unchanged() {
+        let nbt = with("a", NbtList(IntList(vec![1, 2])));
+        assert_eq!(diff(&nbt, &nbt), vec![]);
+    }
+
+    #[test]
+    fn keys() {
+        let old = NbtCompound(Compound::new()
+            .with("changed", 1i32)
+            .with("removed", "gone")
+            .with("nested", Compound::new().with("kept", 1i8)));
+        let new = NbtCompound(Compound::new()
+            .with("added", vec![1i64])
+            .with("nested", Compound::new().with("kept", 1i8).with("added", 2i8))
+            .with("changed", 2i32));
+        let changes = round_trip(old, new);
+        assert_eq!(changes.len(), 4);
+        let path = |s: &str| NbtPath::parse(s).unwrap();
+        assert!(changes.contains(&Removed(path("removed"), NbtString("gone".to_string()))));
+        assert!(changes.contains(&Changed(path("changed"), Int(1), Int(2))));
+        assert!(changes.contains(&Added(path("nested.added"), Byte(2))));
+    }
+
+    #[test]
+    fn lists() {
+        round_trip(with("l", NbtList(DoubleList(vec![1.0, 2.0, 3.0]))),
+                   with("l", NbtList(DoubleList(vec![1.0, 5.0]))));
+        round_trip(with("l", NbtList(IntList(vec![1]))),
+                   with("l", NbtList(IntList(vec![1, 2, 3]))));
+        round_trip(with("l", NbtList(CompoundList(vec![
+                       Compound::new().with("id", "a").with("n", 1i8),
+                       Compound::new().with("id", "b")
+                   ]))),
+                   with("l", NbtList(CompoundList(vec![
+                       Compound::new().with("id", "a").with("m", 1i8)
+                   ]))));
+        round_trip(with("l", NbtList(ListList(vec![ByteList(vec![1]), ByteList(vec![])]))),
+                   with("l", NbtList(ListList(vec![ByteList(vec![2, 3]), ByteList(vec![4])]))));
+        round_trip(with("l", NbtList(List::new())),
+                   with("l", NbtList(StringList(vec!["a".to_string(), "b".to_string()]))));
+    }
+
+    #[test]
+    fn types() {
+        round_trip(Int(1), NbtString("1".to_string()));
+        round_trip(with("t", Byte(1)), with("t", Short(1)));
+        round_trip(with("t", NbtList(IntList(vec![1]))), with("t", NbtList(StringList(vec![]))));
+        round_trip(with("t", NbtList(ByteList(vec![]))), with("t", NbtList(IntList(vec![1]))));
+        round_trip(with("t", NbtList(DoubleList(vec![1.0]))), with("t", NbtList(List::new())));
+        round_trip(with("t", NbtList(ListList(vec![IntList(vec![1])]))),
+                   with("t", NbtList(ListList(vec![LongList(vec![1])]))));
+        round_trip(with("t", IntArray(vec![1])), with("t", NbtCompound(Compound::new())));
+    }
+
+    #[test]
+    fn conflicts() {
+        let changes = diff(&with("a", Int(1)), &with("a", Int(2)));
+        let mut other = with("a", Int(3));
+        assert_eq!(apply(&mut other, changes.as_slice()),
+                   Err(ConflictError(NbtPath::parse("a").unwrap())));
+        let mut missing = NbtCompound(Compound::new());
+        assert_eq!(apply(&mut missing, changes.as_slice()),
+                   Err(MissingError(NbtPath::parse("a").unwrap())));
+
+        let added = diff(&NbtCompound(Compound::new()), &with("a", Int(1)));
+        let mut existing = with("a", Int(1));
+        assert_eq!(apply(&mut existing, added.as_slice()),
+                   Err(ConflictError(NbtPath::parse("a").unwrap())));
+    }
+}
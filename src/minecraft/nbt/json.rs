@@ -0,0 +1,362 @@
+//! Converting NBT to and from JSON, for tools that don't speak NBT.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::num::Float;
+
+use serialize::json::Json;
+
+use super::{ Compound, List, Nbt };
+use super::Nbt::*;
+use super::{ TAG_END, TAG_BYTE, TAG_SHORT, TAG_INT, TAG_LONG, TAG_FLOAT, TAG_DOUBLE,
+             TAG_BYTE_ARRAY, TAG_STRING, TAG_LIST, TAG_COMPOUND, TAG_INT_ARRAY,
+             TAG_LONG_ARRAY };
+
+use self::JsonError::*;
+
+/// An error in typed JSON, with the path of the offending tag.
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonError {
+    UnknownTypeError(String, String),
+    InvalidValueError(String, String),
+    MixedListError(String)
+}
+
+pub type JsonResult<T> = Result<T, JsonError>;
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnknownTypeError(ref t, ref path) => write!(f, "unknown tag type '{}' at '{}'", t, path),
+            InvalidValueError(ref t, ref path) => write!(f, "invalid {} value at '{}'", t, path),
+            MixedListError(ref path) => write!(f, "list elements of different types at '{}'", path)
+        }
+    }
+}
+
+impl Error for JsonError {
+    fn description(&self) -> &str {
+        match *self {
+            UnknownTypeError(..) => "unknown tag type in JSON",
+            InvalidValueError(..) => "invalid tag value in JSON",
+            MixedListError(..) => "list elements of different types in JSON"
+        }
+    }
+}
+
+fn type_name(tag_type: i8) -> &'static str {
+    match tag_type {
+        TAG_BYTE => "byte",
+        TAG_SHORT => "short",
+        TAG_INT => "int",
+        TAG_LONG => "long",
+        TAG_FLOAT => "float",
+        TAG_DOUBLE => "double",
+        TAG_BYTE_ARRAY => "byte_array",
+        TAG_STRING => "string",
+        TAG_LIST => "list",
+        TAG_COMPOUND => "compound",
+        TAG_INT_ARRAY => "int_array",
+        TAG_LONG_ARRAY => "long_array",
+        _ => "end"
+    }
+}
+
+fn named_type(name: &str) -> Option<i8> {
+    Some(match name {
+        "end" => TAG_END,
+        "byte" => TAG_BYTE,
+        "short" => TAG_SHORT,
+        "int" => TAG_INT,
+        "long" => TAG_LONG,
+        "float" => TAG_FLOAT,
+        "double" => TAG_DOUBLE,
+        "byte_array" => TAG_BYTE_ARRAY,
+        "string" => TAG_STRING,
+        "list" => TAG_LIST,
+        "compound" => TAG_COMPOUND,
+        "int_array" => TAG_INT_ARRAY,
+        "long_array" => TAG_LONG_ARRAY,
+        _ => return None
+    })
+}
+
+/// Non-finite numbers can't be written as JSON numbers.
+fn lossy_float(x: f64) -> Json {
+    if x.is_finite() { Json::F64(x) } else { Json::Null }
+}
+
+fn typed_float(x: f64) -> Json {
+    if x.is_nan() {
+        Json::String("NaN".to_string())
+    } else if x.is_infinite() {
+        Json::String(if x > 0.0 { "Infinity" } else { "-Infinity" }.to_string())
+    } else {
+        Json::F64(x)
+    }
+}
+
+fn typed(tag_type: i8, value: Json) -> BTreeMap<String, Json> {
+    let mut obj = BTreeMap::new();
+    obj.insert("type".to_string(), Json::String(type_name(tag_type).to_string()));
+    obj.insert("value".to_string(), value);
+    obj
+}
+
+fn ints<T: Copy, F: Fn(T) -> i64>(v: &[T], f: F) -> Json {
+    Json::Array(v.iter().map(|&x| Json::I64(f(x))).collect())
+}
+
+impl Nbt {
+    /// Converts to plain JSON, as numbers, strings, arrays and objects.
+    /// Tag types are lost, and so are non-finite numbers, which become null.
+    pub fn to_json(&self) -> Json {
+        match *self {
+            Byte(x) => Json::I64(x as i64),
+            Short(x) => Json::I64(x as i64),
+            Int(x) => Json::I64(x as i64),
+            Long(x) => Json::I64(x),
+            Float(x) => lossy_float(x as f64),
+            Double(x) => lossy_float(x),
+            ByteArray(ref v) => ints(v.as_slice(), |x| x as i8 as i64),
+            IntArray(ref v) => ints(v.as_slice(), |x| x as i64),
+            LongArray(ref v) => ints(v.as_slice(), |x| x),
+            NbtString(ref s) => Json::String(s.clone()),
+            NbtList(ref l) => Json::Array(l.to_tags().iter().map(|x| x.to_json()).collect()),
+            NbtCompound(ref c) => {
+                Json::Object(c.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+            }
+        }
+    }
+
+    /// Converts to JSON that keeps tag types, which `from_typed_json`
+    /// turns back into the same tag. Every tag becomes an object like
+    /// `{"type": "short", "value": 3}`; lists also have an `element_type`,
+    /// and compounds hold a list of tags with an extra `name`, to keep
+    /// their order.
+    pub fn to_typed_json(&self) -> Json {
+        let value = match *self {
+            Byte(x) => Json::I64(x as i64),
+            Short(x) => Json::I64(x as i64),
+            Int(x) => Json::I64(x as i64),
+            Long(x) => Json::I64(x),
+            Float(x) => typed_float(x as f64),
+            Double(x) => typed_float(x),
+            NbtList(ref l) => {
+                let mut obj = typed(TAG_LIST, Json::Array(
+                    l.to_tags().iter().map(|x| x.to_typed_json()).collect()
+                ));
+                obj.insert("element_type".to_string(),
+                           Json::String(type_name(l.tag_type()).to_string()));
+                return Json::Object(obj);
+            }
+            NbtCompound(ref c) => Json::Array(c.iter().map(|(k, v)| {
+                match v.to_typed_json() {
+                    Json::Object(mut obj) => {
+                        obj.insert("name".to_string(), Json::String(k.clone()));
+                        Json::Object(obj)
+                    }
+                    _ => unreachable!()
+                }
+            }).collect()),
+            _ => self.to_json()
+        };
+        Json::Object(typed(self.tag_type(), value))
+    }
+
+    /// Converts JSON written by `to_typed_json` back into a tag.
+    pub fn from_typed_json(json: &Json) -> JsonResult<Nbt> {
+        from_typed(json, "")
+    }
+}
+
+fn from_typed(json: &Json, path: &str) -> JsonResult<Nbt> {
+    let name = match json.find("type").and_then(|t| t.as_string()) {
+        Some(t) => t,
+        None => return Err(UnknownTypeError(String::new(), path.to_string()))
+    };
+    let tag_type = match named_type(name) {
+        Some(t) if t != TAG_END => t,
+        _ => return Err(UnknownTypeError(name.to_string(), path.to_string()))
+    };
+    let invalid = || InvalidValueError(name.to_string(), path.to_string());
+    let value = match json.find("value") {
+        Some(v) => v,
+        None => return Err(invalid())
+    };
+
+    macro_rules! int(
+        ($json:expr, $t:ty) => ({
+            match $json.as_i64() {
+                Some(x) if x as $t as i64 == x => x as $t,
+                _ => return Err(invalid())
+            }
+        })
+    );
+    macro_rules! ints(
+        ($t:ty) => ({
+            match value.as_array() {
+                Some(v) => {
+                    let mut ints = Vec::with_capacity(v.len());
+                    for x in v.iter() {
+                        ints.push(int!(x, $t));
+                    }
+                    ints
+                }
+                None => return Err(invalid())
+            }
+        })
+    );
+    let float = |json: &Json| -> Option<f64> {
+        match json.as_string() {
+            Some("NaN") => Some(Float::nan()),
+            Some("Infinity") => Some(Float::infinity()),
+            Some("-Infinity") => Some(Float::neg_infinity()),
+            Some(_) => None,
+            None => json.as_f64()
+        }
+    };
+
+    Ok(match tag_type {
+        TAG_BYTE => Byte(int!(value, i8)),
+        TAG_SHORT => Short(int!(value, i16)),
+        TAG_INT => Int(int!(value, i32)),
+        TAG_LONG => Long(int!(value, i64)),
+        TAG_FLOAT => Float(try!(float(value).ok_or_else(|| invalid())) as f32),
+        TAG_DOUBLE => Double(try!(float(value).ok_or_else(|| invalid()))),
+        TAG_BYTE_ARRAY => ByteArray(ints!(i8).into_iter().map(|x| x as u8).collect()),
+        TAG_INT_ARRAY => IntArray(ints!(i32)),
+        TAG_LONG_ARRAY => LongArray(ints!(i64)),
+        TAG_STRING => match value.as_string() {
+            Some(s) => NbtString(s.to_string()),
+            None => return Err(invalid())
+        },
+        TAG_LIST => {
+            let element_type = json.find("element_type").and_then(|t| t.as_string())
+                .and_then(|t| named_type(t));
+            let (elements, element_type) = match (value.as_array(), element_type) {
+                (Some(v), Some(t)) => (v, t),
+                _ => return Err(invalid())
+            };
+            let mut tags = Vec::with_capacity(elements.len());
+            for (i, x) in elements.iter().enumerate() {
+                tags.push(try!(from_typed(x, format!("{}[{}]", path, i).as_slice())));
+            }
+            if tags.is_empty() {
+                NbtList(try!(List::empty(element_type).ok_or_else(|| invalid())))
+            } else if tags[0].tag_type() != element_type {
+                return Err(MixedListError(path.to_string()));
+            } else {
+                match List::from_tags(tags) {
+                    Ok(l) => NbtList(l),
+                    Err(_) => return Err(MixedListError(path.to_string()))
+                }
+            }
+        }
+        _ => {
+            let entries = match value.as_array() {
+                Some(v) => v,
+                None => return Err(invalid())
+            };
+            let mut c = Compound::with_capacity(entries.len());
+            for entry in entries.iter() {
+                let key = match entry.find("name").and_then(|n| n.as_string()) {
+                    Some(key) => key,
+                    None => return Err(invalid())
+                };
+                let child_path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let tag = try!(from_typed(entry, child_path.as_slice()));
+                c.insert(key.to_string(), tag);
+            }
+            NbtCompound(c)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ f32, f64, i32, i64 };
+    use std::collections::BTreeMap;
+
+    use serialize::json::Json;
+
+    use super::JsonError::*;
+    use super::super::{ Compound, List, Nbt };
+    use super::super::Nbt::*;
+    use super::super::List::*;
+
+    fn every_tag() -> Nbt {
+        NbtCompound(Compound::new()
+            .with("byte", -128i8)
+            .with("short", 32767i16)
+            .with("int", -1i32)
+            .with("long", -(1i64 << 62))
+            .with("float", 0.25f32)
+            .with("double", -1.5f64)
+            .with("infinity", f64::INFINITY)
+            .with("bytes", vec![0u8, 127, 128, 255])
+            .with("ints", vec![i32::MIN, 0])
+            .with("longs", vec![i64::MAX])
+            .with("string", "snow\u{2603}man")
+            .with("empty_bytes", ByteList(vec![]))
+            .with("empty", List::new())
+            .with("lists", ListList(vec![IntList(vec![1, 2]), StringList(vec!["a".to_string()])]))
+            .with("compounds", CompoundList(vec![Compound::new().with("z", 1i8).with("a", 2i8)]))
+            .with("nested", Compound::new().with("b", 0i8).with("a", 0i16)))
+    }
+
+    #[test]
+    fn typed_round_trip() {
+        let nbt = every_tag();
+        let json = nbt.to_typed_json();
+        assert_eq!(Nbt::from_typed_json(&json), Ok(nbt.clone()));
+
+        // Through text too, where integers and floats can't be told apart.
+        let text = json.to_string();
+        let parsed = Json::from_str(text.as_slice()).unwrap();
+        assert_eq!(Nbt::from_typed_json(&parsed), Ok(nbt));
+    }
+
+    #[test]
+    fn typed_nan() {
+        let json = Float(f32::NAN).to_typed_json();
+        match Nbt::from_typed_json(&json) {
+            Ok(Float(x)) => assert!(x.is_nan()),
+            r => panic!("expected a NaN float, got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn typed_errors() {
+        let mut obj = BTreeMap::new();
+        obj.insert("type".to_string(), Json::String("byte".to_string()));
+        obj.insert("value".to_string(), Json::I64(128));
+        assert_eq!(Nbt::from_typed_json(&Json::Object(obj.clone())),
+                   Err(InvalidValueError("byte".to_string(), String::new())));
+        obj.insert("type".to_string(), Json::String("end".to_string()));
+        assert_eq!(Nbt::from_typed_json(&Json::Object(obj)),
+                   Err(UnknownTypeError("end".to_string(), String::new())));
+    }
+
+    #[test]
+    fn lossy() {
+        assert_eq!(Long(1i64 << 40).to_json(), Json::I64(1i64 << 40));
+        assert_eq!(Byte(-1).to_json(), Json::I64(-1));
+        assert_eq!(Double(f64::NAN).to_json(), Json::Null);
+        assert_eq!(ByteArray(vec![255, 1]).to_json(), Json::Array(vec![Json::I64(-1), Json::I64(1)]));
+        assert_eq!(IntArray(vec![-2]).to_json(), Json::Array(vec![Json::I64(-2)]));
+        assert_eq!(LongArray(vec![1i64 << 40]).to_json(), Json::Array(vec![Json::I64(1i64 << 40)]));
+        assert_eq!(NbtList(IntList(vec![3])).to_json(), Json::Array(vec![Json::I64(3)]));
+
+        let mut obj = BTreeMap::new();
+        obj.insert("a".to_string(), Json::String("b".to_string()));
+        obj.insert("l".to_string(), Json::Array(vec![]));
+        let nbt = NbtCompound(Compound::new().with("a", "b").with("l", LongArray(vec![])));
+        assert_eq!(nbt.to_json(), Json::Object(obj));
+    }
+}
@@ -0,0 +1,2129 @@
+use std::borrow::Cow;
+use std::cmp::{ max, min };
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::old_io::{ BufReader, IoError, IoErrorKind, IoResult };
+use std::ops::Index;
+use std::slice;
+use std::str;
+use std::u64;
+use std::usize;
+use std::string::ToString;
+
+use flate::{ deflate_bytes, deflate_bytes_zlib };
+use serialize;
+use serialize::hex::ToHex;
+
+use self::Nbt::*;
+use self::List::*;
+use self::DecoderError::*;
+use self::EncoderError::*;
+use self::NbtError::*;
+
+pub use self::borrowed::{ CompoundRef, ListRef, NbtRef, StrRef };
+pub use self::borrowed::{ IntArrayRef, LongArrayRef };
+pub use self::compound::Compound;
+pub use self::diff::{ Change, PatchError, apply, diff };
+pub use self::json::{ JsonError, JsonResult };
+pub use self::path::{ NbtPath, PathError, PathNode, Target, TargetMut };
+pub use self::snbt::{ Snbt, SnbtError, SnbtResult };
+pub use self::visit::{ NbtVisitor, Visit };
+
+mod borrowed;
+mod compound;
+mod diff;
+mod json;
+mod path;
+mod snbt;
+mod visit;
+
+/// Represents a NBT value
+#[derive(Clone, PartialEq)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+    NbtString(String),
+    NbtList(List),
+    NbtCompound(Compound)
+}
+
+impl fmt::Debug for Nbt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Nbt::Byte(x) => write!(f, "{}b", x),
+            Nbt::Short(x) => write!(f, "{}s", x),
+            Nbt::Int(x) => write!(f, "{}i", x),
+            Nbt::Long(x) => write!(f, "{}L", x),
+            Nbt::Float(x) => write!(f, "{:.1}f", x),
+            Nbt::Double(x) => write!(f, "{:.1}", x),
+            Nbt::ByteArray(ref x) => write!(f, "b<{}>", x.as_slice().to_hex()),
+            Nbt::IntArray(ref x) => write!(f, "{:?}", *x),
+            Nbt::LongArray(ref x) => write!(f, "{:?}", *x),
+            Nbt::NbtString(ref x) => write!(f, "\"{}\"", *x),
+            Nbt::NbtList(ref x) => write!(f, "{:?}", *x),
+            Nbt::NbtCompound(ref x) => write!(f, "{:?}", *x)
+        }
+    }
+}
+
+impl fmt::Display for Nbt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Nbt::Byte(x) => write!(f, "{}", x),
+            Nbt::Short(x) => write!(f, "{}", x),
+            Nbt::Int(x) => write!(f, "{}", x),
+            Nbt::Long(x) => write!(f, "{}", x),
+            Nbt::Float(x) => write!(f, "{:.1}", x),
+            Nbt::Double(x) => write!(f, "{:.1}", x),
+            Nbt::ByteArray(ref x) => write!(f, "<{}>", x.as_slice().to_hex()),
+            Nbt::IntArray(ref x) => write!(f, "{:?}", *x),
+            Nbt::LongArray(ref x) => write!(f, "{:?}", *x),
+            Nbt::NbtString(ref x) => write!(f, "\"{}\"", *x),
+            Nbt::NbtList(ref x) => write!(f, "{:?}", *x),
+            Nbt::NbtCompound(ref x) => write!(f, "{:?}", *x)
+        }
+    }
+}
+
+/// An ordered list of NBT values.
+#[derive(Clone, PartialEq, Debug)]
+pub enum List {
+    ByteList(Vec<i8>),
+    ShortList(Vec<i16>),
+    IntList(Vec<i32>),
+    LongList(Vec<i64>),
+    FloatList(Vec<f32>),
+    DoubleList(Vec<f64>),
+    ByteArrayList(Vec<Vec<u8>>),
+    IntArrayList(Vec<Vec<i32>>),
+    LongArrayList(Vec<Vec<i64>>),
+    StringList(Vec<String>),
+    ListList(Vec<List>),
+    CompoundList(Vec<Compound>)
+}
+
+
+impl List {
+    /// An empty list, which takes on the type of the first element added.
+    pub fn new() -> List {
+        CompoundList(Vec::new())
+    }
+
+    /// The tag type of the elements in this list.
+    ///
+    /// Empty lists are read as an empty `CompoundList`, so that is the
+    /// only empty list written back with the `TAG_End` element type.
+    pub fn tag_type(&self) -> i8 {
+        match *self {
+            ByteList(_) => TAG_BYTE,
+            ShortList(_) => TAG_SHORT,
+            IntList(_) => TAG_INT,
+            LongList(_) => TAG_LONG,
+            FloatList(_) => TAG_FLOAT,
+            DoubleList(_) => TAG_DOUBLE,
+            ByteArrayList(_) => TAG_BYTE_ARRAY,
+            IntArrayList(_) => TAG_INT_ARRAY,
+            LongArrayList(_) => TAG_LONG_ARRAY,
+            StringList(_) => TAG_STRING,
+            ListList(_) => TAG_LIST,
+            CompoundList(ref c) if c.is_empty() => TAG_END,
+            CompoundList(_) => TAG_COMPOUND
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match *self {
+            ByteList(ref v) => v.len(),
+            ShortList(ref v) => v.len(),
+            IntList(ref v) => v.len(),
+            LongList(ref v) => v.len(),
+            FloatList(ref v) => v.len(),
+            DoubleList(ref v) => v.len(),
+            ByteArrayList(ref v) => v.len(),
+            IntArrayList(ref v) => v.len(),
+            LongArrayList(ref v) => v.len(),
+            StringList(ref v) => v.len(),
+            ListList(ref v) => v.len(),
+            CompoundList(ref v) => v.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a copy of an element as a tag.
+    pub fn get(&self, i: usize) -> Option<Nbt> {
+        match *self {
+            ByteList(ref v) => v.get(i).map(|&x| Byte(x)),
+            ShortList(ref v) => v.get(i).map(|&x| Short(x)),
+            IntList(ref v) => v.get(i).map(|&x| Int(x)),
+            LongList(ref v) => v.get(i).map(|&x| Long(x)),
+            FloatList(ref v) => v.get(i).map(|&x| Float(x)),
+            DoubleList(ref v) => v.get(i).map(|&x| Double(x)),
+            ByteArrayList(ref v) => v.get(i).map(|x| ByteArray(x.clone())),
+            IntArrayList(ref v) => v.get(i).map(|x| IntArray(x.clone())),
+            LongArrayList(ref v) => v.get(i).map(|x| LongArray(x.clone())),
+            StringList(ref v) => v.get(i).map(|x| NbtString(x.clone())),
+            ListList(ref v) => v.get(i).map(|x| NbtList(x.clone())),
+            CompoundList(ref v) => v.get(i).map(|x| NbtCompound(x.clone()))
+        }
+    }
+
+    /// Replaces an element, returning the old one. Fails, returning
+    /// `value`, if it's out of bounds or doesn't match the list's type.
+    pub fn set(&mut self, i: usize, value: Nbt) -> Result<Nbt, Nbt> {
+        if i >= self.len() {
+            return Err(value);
+        }
+        Ok(match (self, value) {
+            (&mut ByteList(ref mut v), Byte(x)) => Byte(mem::replace(&mut v[i], x)),
+            (&mut ShortList(ref mut v), Short(x)) => Short(mem::replace(&mut v[i], x)),
+            (&mut IntList(ref mut v), Int(x)) => Int(mem::replace(&mut v[i], x)),
+            (&mut LongList(ref mut v), Long(x)) => Long(mem::replace(&mut v[i], x)),
+            (&mut FloatList(ref mut v), Float(x)) => Float(mem::replace(&mut v[i], x)),
+            (&mut DoubleList(ref mut v), Double(x)) => Double(mem::replace(&mut v[i], x)),
+            (&mut ByteArrayList(ref mut v), ByteArray(x)) => ByteArray(mem::replace(&mut v[i], x)),
+            (&mut IntArrayList(ref mut v), IntArray(x)) => IntArray(mem::replace(&mut v[i], x)),
+            (&mut LongArrayList(ref mut v), LongArray(x)) => LongArray(mem::replace(&mut v[i], x)),
+            (&mut StringList(ref mut v), NbtString(x)) => NbtString(mem::replace(&mut v[i], x)),
+            (&mut ListList(ref mut v), NbtList(x)) => NbtList(mem::replace(&mut v[i], x)),
+            (&mut CompoundList(ref mut v), NbtCompound(x)) => NbtCompound(mem::replace(&mut v[i], x)),
+            (_, value) => return Err(value)
+        })
+    }
+
+    /// Inserts an element, which must match the list's type unless the
+    /// list is the untyped empty list of `List::new`. Empty lists of any
+    /// other type keep it. Fails, returning `value`, like `set`.
+    pub fn insert(&mut self, i: usize, value: Nbt) -> Result<(), Nbt> {
+        if i > self.len() {
+            return Err(value);
+        }
+        if self.tag_type() == TAG_END {
+            *self = List::from_tags(vec![value]).ok().unwrap();
+            return Ok(());
+        }
+        match (self, value) {
+            (&mut ByteList(ref mut v), Byte(x)) => v.insert(i, x),
+            (&mut ShortList(ref mut v), Short(x)) => v.insert(i, x),
+            (&mut IntList(ref mut v), Int(x)) => v.insert(i, x),
+            (&mut LongList(ref mut v), Long(x)) => v.insert(i, x),
+            (&mut FloatList(ref mut v), Float(x)) => v.insert(i, x),
+            (&mut DoubleList(ref mut v), Double(x)) => v.insert(i, x),
+            (&mut ByteArrayList(ref mut v), ByteArray(x)) => v.insert(i, x),
+            (&mut IntArrayList(ref mut v), IntArray(x)) => v.insert(i, x),
+            (&mut LongArrayList(ref mut v), LongArray(x)) => v.insert(i, x),
+            (&mut StringList(ref mut v), NbtString(x)) => v.insert(i, x),
+            (&mut ListList(ref mut v), NbtList(x)) => v.insert(i, x),
+            (&mut CompoundList(ref mut v), NbtCompound(x)) => v.insert(i, x),
+            (_, value) => return Err(value)
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, value: Nbt) -> Result<(), Nbt> {
+        let len = self.len();
+        self.insert(len, value)
+    }
+
+    /// Adds an element, for building lists in one expression:
+    /// `List::new().with(0.5f64).with(64.0f64)`.
+    ///
+    /// Panics if `value` doesn't match the type of the elements so far.
+    pub fn with<V: IntoNbt>(mut self, value: V) -> List {
+        match self.push(value.into_nbt()) {
+            Ok(()) => self,
+            Err(value) => panic!("cannot add {:?} to a list of tag type {}",
+                                 value, self.tag_type())
+        }
+    }
+
+    pub fn remove(&mut self, i: usize) -> Option<Nbt> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(match *self {
+            ByteList(ref mut v) => Byte(v.remove(i)),
+            ShortList(ref mut v) => Short(v.remove(i)),
+            IntList(ref mut v) => Int(v.remove(i)),
+            LongList(ref mut v) => Long(v.remove(i)),
+            FloatList(ref mut v) => Float(v.remove(i)),
+            DoubleList(ref mut v) => Double(v.remove(i)),
+            ByteArrayList(ref mut v) => ByteArray(v.remove(i)),
+            IntArrayList(ref mut v) => IntArray(v.remove(i)),
+            LongArrayList(ref mut v) => LongArray(v.remove(i)),
+            StringList(ref mut v) => NbtString(v.remove(i)),
+            ListList(ref mut v) => NbtList(v.remove(i)),
+            CompoundList(ref mut v) => NbtCompound(v.remove(i))
+        })
+    }
+
+    /// Copies the elements out as individual tags, see `from_tags`.
+    pub fn to_tags(&self) -> Vec<Nbt> {
+        range(0, self.len()).map(|i| self.get(i).unwrap()).collect()
+    }
+
+    /// Builds a list out of individual tags, which must all have the
+    /// same type. The first tag of a different type is returned as error.
+    pub fn from_tags(tags: Vec<Nbt>) -> Result<List, Nbt> {
+        macro_rules! collect(
+            ($tags:expr, $t:ident, $list:ident) => ({
+                let mut v = Vec::with_capacity($tags.len());
+                for tag in $tags.into_iter() {
+                    match tag {
+                        $t(x) => v.push(x),
+                        other => return Err(other)
+                    }
+                }
+                $list(v)
+            })
+        );
+
+        let tag_type = match tags.first() {
+            Some(tag) => tag.tag_type(),
+            None => return Ok(CompoundList(Vec::new()))
+        };
+        Ok(match tag_type {
+            TAG_BYTE => collect!(tags, Byte, ByteList),
+            TAG_SHORT => collect!(tags, Short, ShortList),
+            TAG_INT => collect!(tags, Int, IntList),
+            TAG_LONG => collect!(tags, Long, LongList),
+            TAG_FLOAT => collect!(tags, Float, FloatList),
+            TAG_DOUBLE => collect!(tags, Double, DoubleList),
+            TAG_BYTE_ARRAY => collect!(tags, ByteArray, ByteArrayList),
+            TAG_INT_ARRAY => collect!(tags, IntArray, IntArrayList),
+            TAG_LONG_ARRAY => collect!(tags, LongArray, LongArrayList),
+            TAG_STRING => collect!(tags, NbtString, StringList),
+            TAG_LIST => collect!(tags, NbtList, ListList),
+            _ => collect!(tags, NbtCompound, CompoundList)
+        })
+    }
+
+    /// An empty list of the given element type, `None` for invalid types.
+    pub fn empty(tag_type: i8) -> Option<List> {
+        Some(match tag_type {
+            TAG_BYTE => ByteList(Vec::new()),
+            TAG_SHORT => ShortList(Vec::new()),
+            TAG_INT => IntList(Vec::new()),
+            TAG_LONG => LongList(Vec::new()),
+            TAG_FLOAT => FloatList(Vec::new()),
+            TAG_DOUBLE => DoubleList(Vec::new()),
+            TAG_BYTE_ARRAY => ByteArrayList(Vec::new()),
+            TAG_INT_ARRAY => IntArrayList(Vec::new()),
+            TAG_LONG_ARRAY => LongArrayList(Vec::new()),
+            TAG_STRING => StringList(Vec::new()),
+            TAG_LIST => ListList(Vec::new()),
+            TAG_END | TAG_COMPOUND => CompoundList(Vec::new()),
+            _ => return None
+        })
+    }
+}
+
+impl Nbt {
+    pub fn from_reader<R: Reader>(r: &mut R) -> NbtResult<Nbt> {
+        Nbt::from_reader_with(r, Flavour::Java)
+    }
+
+    pub fn from_reader_with<R: Reader>(r: &mut R, flavour: Flavour) -> NbtResult<Nbt> {
+        match try!(NbtReader::with_flavour(r, flavour).tag()) {
+            Some((nbt, _)) => Ok(nbt),
+            None => Err(UnknownTagError(TAG_END, NbtPos::at(0)))
+        }
+    }
+
+    /// Reads Java NBT within other limits than the default ones.
+    pub fn from_reader_with_limits<R: Reader>(r: &mut R, limits: NbtLimits) -> NbtResult<Nbt> {
+        let mut reader = NbtReader::new(r);
+        reader.set_limits(limits);
+        match try!(reader.tag()) {
+            Some((nbt, _)) => Ok(nbt),
+            None => Err(UnknownTagError(TAG_END, NbtPos::at(0)))
+        }
+    }
+
+    pub fn from_gzip(data: &[u8]) -> NbtResult<Nbt> {
+        Nbt::from_gzip_with_limits(data, NbtLimits::default())
+    }
+
+    /// Reads gzipped NBT, inflating no more than `limits.max_bytes`.
+    pub fn from_gzip_with_limits(data: &[u8], limits: NbtLimits) -> NbtResult<Nbt> {
+        let data = try!(inflate_gzip(data, limits.max_bytes));
+        Nbt::from_reader_with_limits(&mut BufReader::new(data.as_slice()), limits)
+    }
+
+    pub fn from_zlib(data: &[u8]) -> NbtResult<Nbt> {
+        Nbt::from_zlib_with_limits(data, NbtLimits::default())
+    }
+
+    /// Reads zlib compressed NBT, inflating no more than `limits.max_bytes`.
+    pub fn from_zlib_with_limits(data: &[u8], limits: NbtLimits) -> NbtResult<Nbt> {
+        let data = try!(inflate_zlib(data, limits.max_bytes));
+        Nbt::from_reader_with_limits(&mut BufReader::new(data.as_slice()), limits)
+    }
+
+    /// Writes this value as the root tag of an NBT stream, under `name`.
+    pub fn to_writer<W: Writer>(&self, w: &mut W, name: &str) -> IoResult<()> {
+        self.to_writer_with(w, name, Flavour::Java)
+    }
+
+    pub fn to_writer_with<W: Writer>(&self, w: &mut W, name: &str, flavour: Flavour)
+                                     -> IoResult<()> {
+        NbtWriter::with_flavour(w, flavour).tag(self, name)
+    }
+
+    pub fn to_gzip(&self, name: &str) -> IoResult<Vec<u8>> {
+        let mut data = Vec::new();
+        try!(self.to_writer(&mut data, name));
+        deflate_gzip(data.as_slice())
+    }
+
+    pub fn to_zlib(&self, name: &str) -> IoResult<Vec<u8>> {
+        let mut data = Vec::new();
+        try!(self.to_writer(&mut data, name));
+        deflate_zlib(data.as_slice())
+    }
+
+    /// The tag type this value is stored as.
+    pub fn tag_type(&self) -> i8 {
+        match *self {
+            Byte(_) => TAG_BYTE,
+            Short(_) => TAG_SHORT,
+            Int(_) => TAG_INT,
+            Long(_) => TAG_LONG,
+            Float(_) => TAG_FLOAT,
+            Double(_) => TAG_DOUBLE,
+            ByteArray(_) => TAG_BYTE_ARRAY,
+            IntArray(_) => TAG_INT_ARRAY,
+            LongArray(_) => TAG_LONG_ARRAY,
+            NbtString(_) => TAG_STRING,
+            NbtList(_) => TAG_LIST,
+            NbtCompound(_) => TAG_COMPOUND
+        }
+    }
+
+    /// Any integer tag, widened to `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Byte(x) => Some(x as i64),
+            Short(x) => Some(x as i64),
+            Int(x) => Some(x as i64),
+            Long(x) => Some(x),
+            _ => None
+        }
+    }
+
+    /// Any number tag as `f64`, which may round large longs.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Float(x) => Some(x as f64),
+            Double(x) => Some(x),
+            _ => self.as_i64().map(|x| x as f64)
+        }
+    }
+
+    /// Looks up a compound entry, unlike indexing without panicking.
+    pub fn get<'a>(&'a self, key: &str) -> Option<&'a Nbt> {
+        match *self { NbtCompound(ref c) => c.get(key), _ => None }
+    }
+}
+
+/// Generates `as_*` and `as_*_mut` accessors for tags with `Copy` payloads.
+macro_rules! value_accessors(
+    ($($variant:ident: $t:ty, $as_:ident, $as_mut:ident;)*) => (
+        impl Nbt {
+            $(
+                pub fn $as_(&self) -> Option<$t> {
+                    match *self { $variant(x) => Some(x), _ => None }
+                }
+
+                pub fn $as_mut(&mut self) -> Option<&mut $t> {
+                    match *self { $variant(ref mut x) => Some(x), _ => None }
+                }
+            )*
+        }
+    )
+);
+
+/// Generates `as_*`, `as_*_mut` and `into_*` accessors for tags with
+/// owned payloads, borrowed as `$view` by `as_*`.
+macro_rules! owned_accessors(
+    ($($variant:ident: $t:ty, $view:ty, $as_:ident, $as_mut:ident, $into:ident;)*) => (
+        impl Nbt {
+            $(
+                pub fn $as_(&self) -> Option<&$view> {
+                    match *self { $variant(ref x) => Some(&**x), _ => None }
+                }
+
+                pub fn $as_mut(&mut self) -> Option<&mut $t> {
+                    match *self { $variant(ref mut x) => Some(x), _ => None }
+                }
+
+                pub fn $into(self) -> Result<$t, Nbt> {
+                    match self { $variant(x) => Ok(x), x => Err(x) }
+                }
+            )*
+        }
+    )
+);
+
+/// Generates `as_*_list`, `as_*_list_mut` and `into_*_list` accessors
+/// for the elements of each type of list.
+macro_rules! list_accessors(
+    ($($variant:ident: $t:ty, $as_:ident, $as_mut:ident, $into:ident;)*) => (
+        impl Nbt {
+            $(
+                pub fn $as_(&self) -> Option<&[$t]> {
+                    match *self { NbtList($variant(ref v)) => Some(v.as_slice()), _ => None }
+                }
+
+                pub fn $as_mut(&mut self) -> Option<&mut Vec<$t>> {
+                    match *self { NbtList($variant(ref mut v)) => Some(v), _ => None }
+                }
+
+                pub fn $into(self) -> Result<Vec<$t>, Nbt> {
+                    match self { NbtList($variant(v)) => Ok(v), x => Err(x) }
+                }
+            )*
+        }
+    )
+);
+
+value_accessors! {
+    Byte: i8, as_byte, as_byte_mut;
+    Short: i16, as_short, as_short_mut;
+    Int: i32, as_int, as_int_mut;
+    Long: i64, as_long, as_long_mut;
+    Float: f32, as_float, as_float_mut;
+    Double: f64, as_double, as_double_mut;
+}
+
+owned_accessors! {
+    ByteArray: Vec<u8>, [u8], as_byte_array, as_byte_array_mut, into_byte_array;
+    IntArray: Vec<i32>, [i32], as_int_array, as_int_array_mut, into_int_array;
+    LongArray: Vec<i64>, [i64], as_long_array, as_long_array_mut, into_long_array;
+    NbtString: String, str, as_string, as_string_mut, into_string;
+}
+
+list_accessors! {
+    ByteList: i8, as_byte_list, as_byte_list_mut, into_byte_list;
+    ShortList: i16, as_short_list, as_short_list_mut, into_short_list;
+    IntList: i32, as_int_list, as_int_list_mut, into_int_list;
+    LongList: i64, as_long_list, as_long_list_mut, into_long_list;
+    FloatList: f32, as_float_list, as_float_list_mut, into_float_list;
+    DoubleList: f64, as_double_list, as_double_list_mut, into_double_list;
+    ByteArrayList: Vec<u8>, as_byte_array_list, as_byte_array_list_mut, into_byte_array_list;
+    IntArrayList: Vec<i32>, as_int_array_list, as_int_array_list_mut, into_int_array_list;
+    LongArrayList: Vec<i64>, as_long_array_list, as_long_array_list_mut, into_long_array_list;
+    StringList: String, as_string_list, as_string_list_mut, into_string_list;
+    ListList: List, as_list_list, as_list_list_mut, into_list_list;
+    CompoundList: Compound, as_compound_list, as_compound_list_mut, into_compound_list;
+}
+
+/// The names byte arrays had before the accessors were generated.
+impl Nbt {
+    #[deprecated(since = "0.0.0", reason = "renamed to `as_byte_array`")]
+    pub fn as_bytearray(&self) -> Option<&[u8]> {
+        self.as_byte_array()
+    }
+
+    #[deprecated(since = "0.0.0", reason = "renamed to `into_byte_array`")]
+    pub fn into_bytearray(self) -> Result<Vec<u8>, Nbt> {
+        self.into_byte_array()
+    }
+}
+
+impl Nbt {
+    pub fn as_list(&self) -> Option<&List> {
+        match *self { NbtList(ref l) => Some(l), _ => None }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut List> {
+        match *self { NbtList(ref mut l) => Some(l), _ => None }
+    }
+
+    pub fn into_list(self) -> Result<List, Nbt> {
+        match self { NbtList(l) => Ok(l), x => Err(x) }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match *self { NbtCompound(ref c) => Some(c), _ => None }
+    }
+
+    pub fn as_compound_mut(&mut self) -> Option<&mut Compound> {
+        match *self { NbtCompound(ref mut c) => Some(c), _ => None }
+    }
+
+    pub fn into_compound(self) -> Result<Compound, Nbt> {
+        match self { NbtCompound(c) => Ok(c), x => Err(x) }
+    }
+}
+
+/// Conversion of plain values into tags, see `Compound::with`.
+pub trait IntoNbt {
+    fn into_nbt(self) -> Nbt;
+}
+
+macro_rules! into_nbt(
+    ($($t:ty => $variant:ident;)*) => (
+        $(
+            impl IntoNbt for $t {
+                fn into_nbt(self) -> Nbt { $variant(self) }
+            }
+        )*
+    )
+);
+
+into_nbt! {
+    i8 => Byte;
+    i16 => Short;
+    i32 => Int;
+    i64 => Long;
+    f32 => Float;
+    f64 => Double;
+    Vec<u8> => ByteArray;
+    Vec<i32> => IntArray;
+    Vec<i64> => LongArray;
+    String => NbtString;
+    List => NbtList;
+    Compound => NbtCompound;
+}
+
+impl IntoNbt for Nbt {
+    fn into_nbt(self) -> Nbt { self }
+}
+
+/// Booleans are stored as bytes, like vanilla does.
+impl IntoNbt for bool {
+    fn into_nbt(self) -> Nbt { Byte(self as i8) }
+}
+
+impl<'a> IntoNbt for &'a str {
+    fn into_nbt(self) -> Nbt { NbtString(self.to_string()) }
+}
+
+impl<'a> Index<&'a str> for Nbt {
+    type Output = Nbt;
+
+    fn index<'b>(&'b self, s: &&'a str) -> &'b Nbt {
+        match *self {
+            NbtCompound(ref c) => c.get(*s).unwrap(),
+            _ => panic!("cannot index non-compound Nbt ({:?}) with '{}'", self, s)
+        }
+    }
+}
+
+macro_rules! try_opt(
+    ($e:expr) => (match $e { Some(x) => x, None => return None })
+);
+
+pub const TAG_END: i8 = 0;
+pub const TAG_BYTE: i8 = 1;
+pub const TAG_SHORT: i8 = 2;
+pub const TAG_INT: i8 = 3;
+pub const TAG_LONG: i8 = 4;
+pub const TAG_FLOAT: i8 = 5;
+pub const TAG_DOUBLE: i8 = 6;
+pub const TAG_BYTE_ARRAY: i8 = 7;
+pub const TAG_STRING: i8 = 8;
+pub const TAG_LIST: i8 = 9;
+pub const TAG_COMPOUND: i8 = 10;
+pub const TAG_INT_ARRAY: i8 = 11;
+pub const TAG_LONG_ARRAY: i8 = 12;
+
+/// Where in an NBT stream an error was encountered.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NbtPos {
+    /// Byte offset into the stream, or into the compressed data
+    /// for compression errors.
+    pub offset: u64,
+    /// Names of the enclosing tags, with list elements as `[index]`.
+    pub path: Vec<String>
+}
+
+impl NbtPos {
+    pub fn at(offset: u64) -> NbtPos {
+        NbtPos {
+            offset: offset,
+            path: Vec::new()
+        }
+    }
+}
+
+impl fmt::Display for NbtPos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "byte {}", self.offset));
+        if !self.path.is_empty() {
+            try!(write!(f, " in "));
+            for (i, name) in self.path.iter().enumerate() {
+                if i > 0 && !name.starts_with("[") {
+                    try!(write!(f, "."));
+                }
+                try!(write!(f, "{}", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An error encountered while reading NBT.
+#[derive(Clone, PartialEq, Debug)]
+pub enum NbtError {
+    UnknownTagError(i8, NbtPos),
+    InvalidUtf8Error(NbtPos),
+    InvalidVarintError(NbtPos),
+    TruncatedError(NbtPos),
+    DepthLimitError(NbtPos),
+    LengthLimitError(i64, NbtPos),
+    SizeLimitError(NbtPos),
+    CompressionHeaderError(NbtPos),
+    InflateError(NbtPos),
+    ReadError(IoError, NbtPos)
+}
+
+pub type NbtResult<T> = Result<T, NbtError>;
+
+impl NbtError {
+    pub fn pos(&self) -> &NbtPos {
+        match *self {
+            UnknownTagError(_, ref pos) |
+            InvalidUtf8Error(ref pos) |
+            InvalidVarintError(ref pos) |
+            TruncatedError(ref pos) |
+            DepthLimitError(ref pos) |
+            LengthLimitError(_, ref pos) |
+            SizeLimitError(ref pos) |
+            CompressionHeaderError(ref pos) |
+            InflateError(ref pos) |
+            ReadError(_, ref pos) => pos
+        }
+    }
+
+    fn pos_mut(&mut self) -> &mut NbtPos {
+        match *self {
+            UnknownTagError(_, ref mut pos) |
+            InvalidUtf8Error(ref mut pos) |
+            InvalidVarintError(ref mut pos) |
+            TruncatedError(ref mut pos) |
+            DepthLimitError(ref mut pos) |
+            LengthLimitError(_, ref mut pos) |
+            SizeLimitError(ref mut pos) |
+            CompressionHeaderError(ref mut pos) |
+            InflateError(ref mut pos) |
+            ReadError(_, ref mut pos) => pos
+        }
+    }
+
+    /// Prepends the name of an enclosing tag to the error's path.
+    /// Paths are built while unwinding, so reading costs nothing extra.
+    fn within(mut self, name: String) -> NbtError {
+        self.pos_mut().path.insert(0, name);
+        self
+    }
+}
+
+impl fmt::Display for NbtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnknownTagError(tag_type, ref pos) => {
+                write!(f, "unknown tag type {} at {}", tag_type, pos)
+            }
+            LengthLimitError(len, ref pos) => {
+                write!(f, "length {} out of bounds at {}", len, pos)
+            }
+            ReadError(ref e, ref pos) => write!(f, "{} at {}", e, pos),
+            ref e => write!(f, "{} at {}", e.description(), e.pos())
+        }
+    }
+}
+
+impl Error for NbtError {
+    fn description(&self) -> &str {
+        match *self {
+            UnknownTagError(..) => "unknown tag type",
+            InvalidUtf8Error(..) => "invalid UTF-8 in string",
+            InvalidVarintError(..) => "varint too long",
+            TruncatedError(..) => "unexpected end of data",
+            DepthLimitError(..) => "tags nested too deeply",
+            LengthLimitError(..) => "length out of bounds",
+            SizeLimitError(..) => "data too large",
+            CompressionHeaderError(..) => "invalid compression header",
+            InflateError(..) => "inflate failed",
+            ReadError(..) => "read error"
+        }
+    }
+}
+
+/// The byte layouts NBT is stored in. They all hold the same tags,
+/// so any `Nbt` can be read from and written to each of them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Flavour {
+    /// Java Edition: big-endian, with modified UTF-8 strings.
+    Java,
+    /// Bedrock Edition files: little-endian, with UTF-8 strings.
+    Bedrock,
+    /// Bedrock Edition network protocol: like `Bedrock`, but ints, longs
+    /// and lengths are varints, zigzag-encoded except for string lengths.
+    Network
+}
+
+fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+fn unzigzag(x: u64) -> i64 {
+    (x >> 1) as i64 ^ -((x & 1) as i64)
+}
+
+/// Bounds on the data a `NbtReader` accepts, so that untrusted data
+/// can't exhaust memory or overflow the stack.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct NbtLimits {
+    /// How deeply compounds and lists may be nested.
+    pub max_depth: usize,
+    /// The most elements an array, list or string may have.
+    pub max_len: usize,
+    /// The most bytes read from the stream, which also bounds allocations.
+    pub max_bytes: u64
+}
+
+impl NbtLimits {
+    pub fn unlimited() -> NbtLimits {
+        NbtLimits {
+            max_depth: usize::MAX,
+            max_len: usize::MAX,
+            max_bytes: u64::MAX
+        }
+    }
+}
+
+/// Vanilla's depth limit, and sizes well beyond anything vanilla writes.
+impl Default for NbtLimits {
+    fn default() -> NbtLimits {
+        NbtLimits {
+            max_depth: 512,
+            max_len: 1 << 24,
+            max_bytes: 1 << 27
+        }
+    }
+}
+
+/// The most memory reserved for the elements of an array or list before
+/// any of them have been read.
+const MAX_RESERVED_BYTES: usize = 1 << 16;
+
+pub struct NbtReader<'a, R: 'a> {
+    reader: &'a mut R,
+    offset: u64,
+    flavour: Flavour,
+    limits: NbtLimits,
+    depth: usize
+}
+
+impl<'a, R: Reader> NbtReader<'a, R> {
+    pub fn new(reader: &'a mut R) -> NbtReader<'a, R> {
+        NbtReader::with_flavour(reader, Flavour::Java)
+    }
+
+    pub fn with_flavour(reader: &'a mut R, flavour: Flavour) -> NbtReader<'a, R> {
+        NbtReader {
+            reader: reader,
+            offset: 0,
+            flavour: flavour,
+            limits: NbtLimits::default(),
+            depth: 0
+        }
+    }
+
+    /// Replaces the default limits, see `NbtLimits`.
+    pub fn set_limits(&mut self, limits: NbtLimits) {
+        self.limits = limits;
+    }
+
+    /// The number of bytes read so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn flavour(&self) -> Flavour {
+        self.flavour
+    }
+
+    fn read<T, F>(&mut self, size: u64, read: F) -> NbtResult<T>
+        where F: FnOnce(&mut R) -> IoResult<T>
+    {
+        if size > self.limits.max_bytes - self.offset {
+            return Err(SizeLimitError(NbtPos::at(self.offset)));
+        }
+        match read(&mut *self.reader) {
+            Ok(x) => {
+                self.offset += size;
+                Ok(x)
+            }
+            Err(ref e) if e.kind == IoErrorKind::EndOfFile => {
+                Err(TruncatedError(NbtPos::at(self.offset)))
+            }
+            Err(e) => Err(ReadError(e, NbtPos::at(self.offset)))
+        }
+    }
+
+    /// Reads a little-endian base 128 varint of at most `max_len` bytes.
+    fn varint(&mut self, max_len: usize) -> NbtResult<u64> {
+        let start = self.offset;
+        let mut x = 0u64;
+        for i in range(0, max_len) {
+            let b = try!(self.read(1, |r| r.read_u8()));
+            x |= ((b & 0x7f) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(InvalidVarintError(NbtPos::at(start)))
+    }
+
+    fn i8(&mut self) -> NbtResult<i8> { self.read(1, |r| r.read_i8()) }
+
+    fn i16(&mut self) -> NbtResult<i16> {
+        match self.flavour {
+            Flavour::Java => self.read(2, |r| r.read_be_i16()),
+            _ => self.read(2, |r| r.read_le_i16())
+        }
+    }
+
+    fn i32(&mut self) -> NbtResult<i32> {
+        match self.flavour {
+            Flavour::Java => self.read(4, |r| r.read_be_i32()),
+            Flavour::Bedrock => self.read(4, |r| r.read_le_i32()),
+            Flavour::Network => self.varint(5).map(|x| unzigzag(x) as i32)
+        }
+    }
+
+    fn i64(&mut self) -> NbtResult<i64> {
+        match self.flavour {
+            Flavour::Java => self.read(8, |r| r.read_be_i64()),
+            Flavour::Bedrock => self.read(8, |r| r.read_le_i64()),
+            Flavour::Network => self.varint(10).map(unzigzag)
+        }
+    }
+
+    fn f32(&mut self) -> NbtResult<f32> {
+        match self.flavour {
+            Flavour::Java => self.read(4, |r| r.read_be_f32()),
+            _ => self.read(4, |r| r.read_le_f32())
+        }
+    }
+
+    fn f64(&mut self) -> NbtResult<f64> {
+        match self.flavour {
+            Flavour::Java => self.read(8, |r| r.read_be_f64()),
+            _ => self.read(8, |r| r.read_le_f64())
+        }
+    }
+
+    fn check_len(&self, len: i64, start: u64) -> NbtResult<usize> {
+        if len < 0 || len as u64 > self.limits.max_len as u64 {
+            return Err(LengthLimitError(len, NbtPos::at(start)));
+        }
+        Ok(len as usize)
+    }
+
+    /// Reads the length of an array or list.
+    fn len(&mut self) -> NbtResult<usize> {
+        let start = self.offset;
+        let len = try!(self.i32());
+        self.check_len(len as i64, start)
+    }
+
+    fn string_len(&mut self) -> NbtResult<usize> {
+        let start = self.offset;
+        let len = match self.flavour {
+            Flavour::Java => try!(self.read(2, |r| r.read_be_u16())) as i64,
+            Flavour::Bedrock => try!(self.read(2, |r| r.read_le_u16())) as i64,
+            Flavour::Network => try!(self.varint(5)) as i64
+        };
+        self.check_len(len, start)
+    }
+
+    /// Reads a compound or list, one level deeper.
+    fn nested<T, F>(&mut self, read: F) -> NbtResult<T>
+        where F: FnOnce(&mut NbtReader<R>) -> NbtResult<T>
+    {
+        if self.depth >= self.limits.max_depth {
+            return Err(DepthLimitError(NbtPos::at(self.offset)));
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn bytes(&mut self, len: usize) -> NbtResult<Vec<u8>> {
+        self.read(len as u64, |r| r.read_exact(len))
+    }
+
+    fn string(&mut self) -> NbtResult<String> {
+        let len = try!(self.string_len());
+        let start = self.offset;
+        let bytes = try!(self.bytes(len));
+        let s = match self.flavour {
+            Flavour::Java => decode_mutf8(bytes.as_slice()),
+            _ => String::from_utf8(bytes).ok()
+        };
+        s.ok_or(InvalidUtf8Error(NbtPos::at(start)))
+    }
+
+    fn array_u8(&mut self) -> NbtResult<Vec<u8>> {
+        let len = try!(self.len());
+        self.bytes(len)
+    }
+
+    fn array<T, F>(&mut self, mut read: F) -> NbtResult<Vec<T>>
+        where F: FnMut(&mut NbtReader<R>) -> NbtResult<T>
+    {
+        let len = try!(self.len());
+        // Elements take far more memory than the bytes they're read from,
+        // so a bogus length only reserves a little, and the rest is grown
+        // into as elements are actually read.
+        let capacity = min(len, MAX_RESERVED_BYTES / max(mem::size_of::<T>(), 1));
+        let mut v = Vec::with_capacity(capacity);
+        for i in range(0, len) {
+            match read(self) {
+                Ok(x) => v.push(x),
+                Err(e) => return Err(e.within(format!("[{}]", i)))
+            }
+        }
+        Ok(v)
+    }
+
+    /// Error for a tag type byte that was just read.
+    fn unknown_tag(&self, tag_type: i8) -> NbtError {
+        UnknownTagError(tag_type, NbtPos::at(self.offset - 1))
+    }
+
+    fn compound(&mut self) -> NbtResult<Compound> {
+        let mut map = Compound::new();
+        loop {
+            match try!(self.tag()) {
+                Some((v, name)) => {
+                    map.insert(name, v);
+                }
+                None => break
+            }
+        }
+        Ok(map)
+    }
+
+    fn list(&mut self) -> NbtResult<List> {
+        match try!(self.i8()) {
+            TAG_END => {
+                let start = self.offset - 1;
+                match try!(self.len()) {
+                    0 => Ok(CompoundList(Vec::new())),
+                    _ => Err(UnknownTagError(TAG_END, NbtPos::at(start)))
+                }
+            }
+            TAG_BYTE => self.array(|r| r.i8()).map(ByteList),
+            TAG_SHORT => self.array(|r| r.i16()).map(ShortList),
+            TAG_INT => self.array(|r| r.i32()).map(IntList),
+            TAG_LONG => self.array(|r| r.i64()).map(LongList),
+            TAG_FLOAT => self.array(|r| r.f32()).map(FloatList),
+            TAG_DOUBLE => self.array(|r| r.f64()).map(DoubleList),
+            TAG_BYTE_ARRAY => self.array(|r| r.array_u8()).map(ByteArrayList),
+            TAG_INT_ARRAY => self.array(|r| r.array(|r| r.i32())).map(IntArrayList),
+            TAG_LONG_ARRAY => self.array(|r| r.array(|r| r.i64())).map(LongArrayList),
+            TAG_STRING => self.array(|r| r.string()).map(StringList),
+            TAG_LIST => self.array(|r| r.nested(|r| r.list())).map(ListList),
+            TAG_COMPOUND => self.array(|r| r.nested(|r| r.compound())).map(CompoundList),
+            tag_type => Err(self.unknown_tag(tag_type))
+        }
+    }
+
+    pub fn tag(&mut self) -> NbtResult<Option<(Nbt, String)>> {
+        Ok(match try!(self.i8()) {
+            TAG_END => None,
+            tag_type if tag_type < TAG_END || tag_type > TAG_LONG_ARRAY => {
+                return Err(self.unknown_tag(tag_type))
+            }
+            tag_type => {
+                let name = try!(self.string());
+                match self.payload(tag_type) {
+                    Ok(value) => Some((value, name)),
+                    Err(e) => return Err(e.within(name))
+                }
+            }
+        })
+    }
+
+    /// Reads the payload of a tag of a known, valid type.
+    fn payload(&mut self, tag_type: i8) -> NbtResult<Nbt> {
+        match tag_type {
+            TAG_BYTE => self.i8().map(Byte),
+            TAG_SHORT => self.i16().map(Short),
+            TAG_INT => self.i32().map(Int),
+            TAG_LONG => self.i64().map(Long),
+            TAG_FLOAT => self.f32().map(Float),
+            TAG_DOUBLE => self.f64().map(Double),
+            TAG_BYTE_ARRAY => self.array_u8().map(ByteArray),
+            TAG_INT_ARRAY => self.array(|r| r.i32()).map(IntArray),
+            TAG_LONG_ARRAY => self.array(|r| r.i64()).map(LongArray),
+            TAG_STRING => self.string().map(NbtString),
+            TAG_LIST => self.nested(|r| r.list()).map(NbtList),
+            _ => self.nested(|r| r.compound()).map(NbtCompound)
+        }
+    }
+}
+
+/// Decodes Java's "modified UTF-8", as used for NBT strings.
+///
+/// It differs from UTF-8 in encoding NUL as `0xC0 0x80` and supplementary
+/// characters as two 3-byte surrogates. Plain 4-byte UTF-8 sequences are
+/// accepted as well, while unpaired surrogates become U+FFFD.
+pub fn decode_mutf8(bytes: &[u8]) -> Option<String> {
+    // Without NULs or supplementary characters, MUTF-8 is plain UTF-8.
+    match str::from_utf8(bytes) {
+        Ok(s) => return Some(s.to_string()),
+        Err(_) => {}
+    }
+
+    fn continuation(bytes: &[u8], i: usize) -> Option<u32> {
+        match bytes.get(i) {
+            Some(&b) if b & 0xc0 == 0x80 => Some((b & 0x3f) as u32),
+            _ => None
+        }
+    }
+
+    let mut units: Vec<u16> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i] as u32;
+        if b < 0x80 {
+            units.push(b as u16);
+            i += 1;
+        } else if b & 0xe0 == 0xc0 {
+            let c1 = try_opt!(continuation(bytes, i + 1));
+            units.push(((b & 0x1f) << 6 | c1) as u16);
+            i += 2;
+        } else if b & 0xf0 == 0xe0 {
+            let c1 = try_opt!(continuation(bytes, i + 1));
+            let c2 = try_opt!(continuation(bytes, i + 2));
+            units.push(((b & 0x0f) << 12 | c1 << 6 | c2) as u16);
+            i += 3;
+        } else if b & 0xf8 == 0xf0 {
+            let c1 = try_opt!(continuation(bytes, i + 1));
+            let c2 = try_opt!(continuation(bytes, i + 2));
+            let c3 = try_opt!(continuation(bytes, i + 3));
+            // Overlong encodings of BMP characters underflow here.
+            let c = try_opt!(((b & 0x07) << 18 | c1 << 12 | c2 << 6 | c3).checked_sub(0x10000));
+            if c > 0xfffff {
+                return None;
+            }
+            units.push((0xd800 | (c >> 10)) as u16);
+            units.push((0xdc00 | (c & 0x3ff)) as u16);
+            i += 4;
+        } else {
+            return None;
+        }
+    }
+    Some(String::from_utf16_lossy(units.as_slice()))
+}
+
+/// Encodes a string as Java's "modified UTF-8", see `decode_mutf8`.
+pub fn encode_mutf8<'a>(s: &'a str) -> Cow<'a, [u8]> {
+    if !s.bytes().any(|b| b == 0 || b >= 0xf0) {
+        return Cow::Borrowed(s.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(s.len() + 2);
+    for unit in s.utf16_units() {
+        match unit {
+            0x01...0x7f => bytes.push(unit as u8),
+            0 | 0x80...0x7ff => {
+                bytes.push(0xc0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                bytes.push(0xe0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+const GZIP_FHCRC: u8 = 0x02;
+const GZIP_FEXTRA: u8 = 0x04;
+const GZIP_FNAME: u8 = 0x08;
+const GZIP_FCOMMENT: u8 = 0x10;
+
+/// The part of miniz that `flate` doesn't expose: inflating into a
+/// callback, which can stop it before the output gets too large.
+mod miniz {
+    pub const TINFL_FLAG_PARSE_ZLIB_HEADER: i32 = 1;
+
+    pub type PutBuf = extern "C" fn(buf: *const u8, len: i32, user: *mut u8) -> i32;
+
+    extern {
+        pub fn tinfl_decompress_mem_to_callback(in_buf: *const u8, in_buf_size: *mut usize,
+                                                put_buf: PutBuf, user: *mut u8,
+                                                flags: i32) -> i32;
+    }
+}
+
+/// Collects inflated data, as long as it stays within `max_len`.
+struct InflateSink {
+    out: Vec<u8>,
+    max_len: u64,
+    over_limit: bool
+}
+
+extern "C" fn put_inflated(buf: *const u8, len: i32, user: *mut u8) -> i32 {
+    let sink = unsafe { &mut *(user as *mut InflateSink) };
+    if (sink.out.len() + len as usize) as u64 > sink.max_len {
+        sink.over_limit = true;
+        return 0;
+    }
+    sink.out.push_all(unsafe { slice::from_raw_parts(buf, len as usize) });
+    1
+}
+
+/// Inflates `data`, which starts at `pos` of the compressed stream, and
+/// stops with `SizeLimitError` as soon as it would exceed `max_len`.
+fn inflate_limited(data: &[u8], pos: usize, flags: i32, max_len: u64) -> NbtResult<Vec<u8>> {
+    let mut sink = InflateSink { out: Vec::new(), max_len: max_len, over_limit: false };
+    let mut len = data.len();
+    let done = unsafe {
+        miniz::tinfl_decompress_mem_to_callback(data.as_ptr(), &mut len, put_inflated,
+                                                &mut sink as *mut InflateSink as *mut u8,
+                                                flags)
+    };
+    if sink.over_limit {
+        Err(SizeLimitError(NbtPos::at(max_len)))
+    } else if done == 0 {
+        Err(InflateError(NbtPos::at((pos + len) as u64)))
+    } else {
+        Ok(sink.out)
+    }
+}
+
+/// Skips the gzip header (RFC 1952) and inflates the deflate stream,
+/// into at most `max_len` bytes.
+pub fn inflate_gzip(data: &[u8], max_len: u64) -> NbtResult<Vec<u8>> {
+    if data.len() < 10 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 0x08 {
+        return Err(CompressionHeaderError(NbtPos::at(0)));
+    }
+    let flags = data[3];
+    let mut i = 10;
+    if flags & GZIP_FEXTRA != 0 {
+        if data.len() < i + 2 {
+            return Err(CompressionHeaderError(NbtPos::at(i as u64)));
+        }
+        i += 2 + (data[i] as usize | (data[i + 1] as usize) << 8);
+    }
+    for &flag in [GZIP_FNAME, GZIP_FCOMMENT].iter() {
+        if flags & flag != 0 && i <= data.len() {
+            // Zero-terminated file name or comment.
+            match data[i..].iter().position(|&b| b == 0) {
+                Some(len) => i += len + 1,
+                None => return Err(CompressionHeaderError(NbtPos::at(i as u64)))
+            }
+        }
+    }
+    if flags & GZIP_FHCRC != 0 {
+        i += 2;
+    }
+    if i > data.len() {
+        return Err(CompressionHeaderError(NbtPos::at(data.len() as u64)));
+    }
+    inflate_limited(&data[i..], i, 0, max_len)
+}
+
+/// Inflates a zlib stream into at most `max_len` bytes.
+pub fn inflate_zlib(data: &[u8], max_len: u64) -> NbtResult<Vec<u8>> {
+    inflate_limited(data, 0, miniz::TINFL_FLAG_PARSE_ZLIB_HEADER, max_len)
+}
+
+pub fn deflate_gzip(data: &[u8]) -> IoResult<Vec<u8>> {
+    let compressed = try!(deflate_bytes(data).ok_or(deflate_error()));
+
+    // Minimal gzip header: deflate, no flags, no mtime, unknown OS.
+    let mut out = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0xff];
+    out.push_all(compressed.as_slice());
+    try!(out.write_le_u32(crc32(data)));
+    try!(out.write_le_u32(data.len() as u32));
+    Ok(out)
+}
+
+pub fn deflate_zlib(data: &[u8]) -> IoResult<Vec<u8>> {
+    let compressed = try!(deflate_bytes_zlib(data).ok_or(deflate_error()));
+    Ok(compressed.as_slice().to_vec())
+}
+
+pub struct NbtWriter<'a, W: 'a> {
+    writer: &'a mut W,
+    flavour: Flavour
+}
+
+impl<'a, W: Writer> NbtWriter<'a, W> {
+    pub fn new(writer: &'a mut W) -> NbtWriter<'a, W> {
+        NbtWriter::with_flavour(writer, Flavour::Java)
+    }
+
+    pub fn with_flavour(writer: &'a mut W, flavour: Flavour) -> NbtWriter<'a, W> {
+        NbtWriter {
+            writer: writer,
+            flavour: flavour
+        }
+    }
+
+    pub fn flavour(&self) -> Flavour {
+        self.flavour
+    }
+
+    fn varint(&mut self, mut x: u64) -> IoResult<()> {
+        while x >= 0x80 {
+            try!(self.writer.write_u8(x as u8 | 0x80));
+            x >>= 7;
+        }
+        self.writer.write_u8(x as u8)
+    }
+
+    fn i8(&mut self, x: i8) -> IoResult<()> { self.writer.write_i8(x) }
+
+    fn i16(&mut self, x: i16) -> IoResult<()> {
+        match self.flavour {
+            Flavour::Java => self.writer.write_be_i16(x),
+            _ => self.writer.write_le_i16(x)
+        }
+    }
+
+    fn i32(&mut self, x: i32) -> IoResult<()> {
+        match self.flavour {
+            Flavour::Java => self.writer.write_be_i32(x),
+            Flavour::Bedrock => self.writer.write_le_i32(x),
+            Flavour::Network => self.varint(zigzag(x as i64))
+        }
+    }
+
+    fn i64(&mut self, x: i64) -> IoResult<()> {
+        match self.flavour {
+            Flavour::Java => self.writer.write_be_i64(x),
+            Flavour::Bedrock => self.writer.write_le_i64(x),
+            Flavour::Network => self.varint(zigzag(x))
+        }
+    }
+
+    fn f32(&mut self, x: f32) -> IoResult<()> {
+        match self.flavour {
+            Flavour::Java => self.writer.write_be_f32(x),
+            _ => self.writer.write_le_f32(x)
+        }
+    }
+
+    fn f64(&mut self, x: f64) -> IoResult<()> {
+        match self.flavour {
+            Flavour::Java => self.writer.write_be_f64(x),
+            _ => self.writer.write_le_f64(x)
+        }
+    }
+
+    fn string(&mut self, s: &str) -> IoResult<()> {
+        let bytes = match self.flavour {
+            Flavour::Java => encode_mutf8(s),
+            _ => Cow::Borrowed(s.as_bytes())
+        };
+        if bytes.len() > 0xffff && self.flavour != Flavour::Network {
+            return Err(IoError {
+                kind: IoErrorKind::InvalidInput,
+                desc: "string too long for NBT",
+                detail: Some(format!("{} bytes", bytes.len()))
+            });
+        }
+        try!(match self.flavour {
+            Flavour::Java => self.writer.write_be_u16(bytes.len() as u16),
+            Flavour::Bedrock => self.writer.write_le_u16(bytes.len() as u16),
+            Flavour::Network => self.varint(bytes.len() as u64)
+        });
+        self.writer.write_all(&*bytes)
+    }
+
+    fn array_u8(&mut self, v: &[u8]) -> IoResult<()> {
+        try!(self.i32(v.len() as i32));
+        self.writer.write_all(v)
+    }
+
+    fn array<T, F>(&mut self, v: &[T], mut write: F) -> IoResult<()>
+        where F: FnMut(&mut NbtWriter<W>, &T) -> IoResult<()>
+    {
+        try!(self.i32(v.len() as i32));
+        for x in v.iter() {
+            try!(write(self, x))
+        }
+        Ok(())
+    }
+
+    fn compound(&mut self, c: &Compound) -> IoResult<()> {
+        for (name, v) in c.iter() {
+            try!(self.tag(v, name.as_slice()));
+        }
+        self.i8(TAG_END)
+    }
+
+    fn list(&mut self, list: &List) -> IoResult<()> {
+        try!(self.i8(list.tag_type()));
+        match *list {
+            ByteList(ref v) => self.array(v.as_slice(), |w, &x| w.i8(x)),
+            ShortList(ref v) => self.array(v.as_slice(), |w, &x| w.i16(x)),
+            IntList(ref v) => self.array(v.as_slice(), |w, &x| w.i32(x)),
+            LongList(ref v) => self.array(v.as_slice(), |w, &x| w.i64(x)),
+            FloatList(ref v) => self.array(v.as_slice(), |w, &x| w.f32(x)),
+            DoubleList(ref v) => self.array(v.as_slice(), |w, &x| w.f64(x)),
+            ByteArrayList(ref v) => self.array(v.as_slice(), |w, x| w.array_u8(x.as_slice())),
+            IntArrayList(ref v) => self.array(v.as_slice(),
+                |w, x| w.array(x.as_slice(), |w, &x| w.i32(x))),
+            LongArrayList(ref v) => self.array(v.as_slice(),
+                |w, x| w.array(x.as_slice(), |w, &x| w.i64(x))),
+            StringList(ref v) => self.array(v.as_slice(), |w, x| w.string(x.as_slice())),
+            ListList(ref v) => self.array(v.as_slice(), |w, x| w.list(x)),
+            CompoundList(ref v) => self.array(v.as_slice(), |w, x| w.compound(x))
+        }
+    }
+
+    fn payload(&mut self, nbt: &Nbt) -> IoResult<()> {
+        match *nbt {
+            Byte(x) => self.i8(x),
+            Short(x) => self.i16(x),
+            Int(x) => self.i32(x),
+            Long(x) => self.i64(x),
+            Float(x) => self.f32(x),
+            Double(x) => self.f64(x),
+            ByteArray(ref v) => self.array_u8(v.as_slice()),
+            IntArray(ref v) => self.array(v.as_slice(), |w, &x| w.i32(x)),
+            LongArray(ref v) => self.array(v.as_slice(), |w, &x| w.i64(x)),
+            NbtString(ref s) => self.string(s.as_slice()),
+            NbtList(ref l) => self.list(l),
+            NbtCompound(ref c) => self.compound(c)
+        }
+    }
+
+    /// Writes a named tag, the counterpart of `NbtReader::tag`.
+    pub fn tag(&mut self, nbt: &Nbt, name: &str) -> IoResult<()> {
+        try!(self.i8(nbt.tag_type()));
+        try!(self.string(name));
+        self.payload(nbt)
+    }
+}
+
+fn deflate_error() -> IoError {
+    IoError {
+        kind: IoErrorKind::OtherIoError,
+        desc: "deflate failed",
+        detail: None
+    }
+}
+
+/// CRC-32 (IEEE) checksum, as required by the gzip trailer.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data.iter() {
+        crc ^= b as u32;
+        for _ in range(0, 8) {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A structure to decode NBT to values in rust.
+pub struct Decoder {
+    stack: Vec<DecodeResult<Nbt>>
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DecoderError {
+    ExpectedError(String, String),
+    MissingFieldError(String),
+    UnknownVariantError(String),
+    ApplicationError(String)
+}
+
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+impl Decoder {
+    /// Creates a new decoder instance for decoding the specified NBT value.
+    pub fn new(nbt: Nbt) -> Decoder {
+        Decoder {
+            stack: vec![Ok(nbt)]
+        }
+    }
+    fn pop(&mut self) -> DecodeResult<Nbt> {
+        self.stack.pop().unwrap()
+    }
+    fn push(&mut self, nbt: Nbt) {
+        self.stack.push(Ok(nbt))
+    }
+    fn push_all<T, F>(&mut self, list: Vec<T>, f: F) -> usize
+        where F: FnMut(T) -> Nbt
+    {
+        let len = list.len();
+        self.stack.extend(list.into_iter().rev().map(f).map(Ok::<Nbt, DecoderError>));
+        len
+    }
+}
+
+// impl Decodable for Nbt {
+//     fn decode<D: serialize::Decoder>(d: &mut D) -> Result<Self, serialize::Decoder::Error> {
+//         d.pop()
+//     }
+// }
+
+macro_rules! expect(
+    ($s:expr, $t:ident) => ({
+        match $s.pop() {
+            Ok($t(v)) => Ok(v),
+            Ok(other) => {
+                Err(ExpectedError(stringify!($t).to_string(), other.to_string()))
+            }
+            Err(e) => Err(e)
+        }
+    });
+    ($s:expr, $t:ident as $to:ty) => (expect!($s, $t).map(|x| x as $to))
+);
+
+impl serialize::Decoder for Decoder {
+    type Error = DecoderError;
+
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        Err(ExpectedError("()".to_string(), try!(self.pop()).to_string()))
+    }
+
+    fn read_u64(&mut self) -> DecodeResult<u64> { expect!(self, Long as u64) }
+    fn read_u32(&mut self) -> DecodeResult<u32> { expect!(self, Int as u32) }
+    fn read_u16(&mut self) -> DecodeResult<u16> { expect!(self, Short as u16) }
+    fn read_u8 (&mut self) -> DecodeResult<u8>  { expect!(self, Byte as u8) }
+
+    fn read_i64(&mut self) -> DecodeResult<i64> { expect!(self, Long) }
+    fn read_i32(&mut self) -> DecodeResult<i32> { expect!(self, Int) }
+    fn read_i16(&mut self) -> DecodeResult<i16> { expect!(self, Short) }
+    fn read_i8 (&mut self) -> DecodeResult<i8>  { expect!(self, Byte) }
+
+    fn read_isize(&mut self) -> DecodeResult<isize> {
+        match try!(self.pop()) {
+            Byte(x) => Ok(x as isize),
+            Short(x) => Ok(x as isize),
+            Int(x) => Ok(x as isize),
+            Long(x) => Ok(x as isize),
+            other => Err(ExpectedError("isize".to_string(), other.to_string()))
+        }
+    }
+    fn read_usize(&mut self) -> DecodeResult<usize> {
+        Ok(try!(self.read_isize()) as usize)
+    }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        Ok(try!(self.read_u8()) != 0)
+    }
+
+    fn read_f64(&mut self) -> DecodeResult<f64> { expect!(self, Double) }
+    fn read_f32(&mut self) -> DecodeResult<f32> { expect!(self, Float) }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let s = try!(self.read_str());
+        {
+            let mut it = s.as_slice().chars();
+            match (it.next(), it.next()) {
+                // exactly one character
+                (Some(c), None) => return Ok(c),
+                _ => ()
+            }
+        }
+        Err(ExpectedError("single character string".to_string(), s))
+    }
+
+    fn read_str(&mut self) -> DecodeResult<String> {
+        expect!(self, NbtString)
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Self, usize) -> DecodeResult<T>
+    {
+        let name = match try!(self.pop()) {
+            NbtString(s) => s,
+            NbtCompound(mut o) => {
+                let name = match o.remove("variant") {
+                    Some(NbtString(s)) => s,
+                    Some(val) => return Err(ExpectedError("String".to_string(), val.to_string())),
+                    None => return Err(MissingFieldError("variant".to_string()))
+                };
+                match o.remove("fields") {
+                    Some(v) => {
+                        self.push(v);
+                        try!(self.read_seq(|_, _| Ok(())));
+                    },
+                    None => return Err(MissingFieldError("fields".to_string()))
+                }
+                name
+            }
+            nbt => {
+                return Err(ExpectedError("String or Compound".to_string(), nbt.to_string()))
+            }
+        };
+        let idx = match names.iter().position(|n| *n == name.as_slice()) {
+            Some(idx) => idx,
+            None => return Err(UnknownVariantError(name))
+        };
+        f(self, idx)
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Self, usize) -> DecodeResult<T>
+    {
+        self.read_enum_variant(names, f)
+    }
+
+
+    fn read_enum_struct_variant_field<T, F>(&mut self, _name: &str, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        let value = try!(f(self));
+        let _ = self.pop();
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self, name: &str, _idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        let mut obj = try!(expect!(self, NbtCompound));
+
+        // A missing field is handed to `f` as an error, so that
+        // `read_option` can turn it into `None`.
+        let value = match obj.remove(name) {
+            None => {
+                self.stack.push(Err(MissingFieldError(name.to_string())));
+                try!(f(self))
+            }
+            Some(v) => {
+                self.stack.push(Ok(v));
+                try!(f(self))
+            }
+        };
+        self.push(NbtCompound(obj));
+        Ok(value)
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        self.read_seq(move |d, len| {
+            if len == tuple_len {
+                f(d)
+            } else {
+                Err(ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", len)))
+            }
+        })
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self, _name: &str, len: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Self, bool) -> DecodeResult<T>
+    {
+        match self.pop() {
+            Ok(value) => { self.push(value); f(self, true) }
+            Err(MissingFieldError(_)) => f(self, false),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self, usize) -> DecodeResult<T>
+    {
+        // Arrays are sequences too, e.g. a `Vec<i64>` from a `LongArray`.
+        let list = match try!(self.pop()) {
+            NbtList(list) => list,
+            ByteArray(array) => ByteList(array.into_iter().map(|x| x as i8).collect()),
+            IntArray(array) => IntList(array),
+            LongArray(array) => LongList(array),
+            other => return Err(ExpectedError("NbtList".to_string(), other.to_string()))
+        };
+        let len = match list {
+            ByteList(list) => self.push_all(list, Byte),
+            ShortList(list) => self.push_all(list, Short),
+            IntList(list) => self.push_all(list, Int),
+            LongList(list) => self.push_all(list, Long),
+            FloatList(list) => self.push_all(list, Float),
+            DoubleList(list) => self.push_all(list, Double),
+            ByteArrayList(list) => self.push_all(list, ByteArray),
+            IntArrayList(list) => self.push_all(list, IntArray),
+            LongArrayList(list) => self.push_all(list, LongArray),
+            StringList(list) => self.push_all(list, NbtString),
+            ListList(list) => self.push_all(list, NbtList),
+            CompoundList(list) => self.push_all(list, NbtCompound)
+        };
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self, usize) -> DecodeResult<T>
+    {
+        let obj = try!(expect!(self, NbtCompound));
+        let len = obj.len();
+        for (key, value) in obj.into_iter() {
+            self.push(value);
+            self.push(NbtString(key));
+        }
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Self) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+/// A structure to encode values in rust to NBT.
+pub struct Encoder {
+    // `None` marks an omitted value, i.e. an `Option` that was `None`.
+    stack: Vec<Option<Nbt>>,
+    keys: Vec<String>
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EncoderError {
+    UnsupportedError(String),
+    MixedListError(String, String),
+    KeyError(String)
+}
+
+pub type EncodeResult<T> = Result<T, EncoderError>;
+
+/// Encodes a value in rust to NBT.
+pub fn encode<T: serialize::Encodable>(value: &T) -> EncodeResult<Nbt> {
+    let mut encoder = Encoder::new();
+    try!(value.encode(&mut encoder));
+    encoder.into_nbt()
+}
+
+impl Encoder {
+    /// Creates a new encoder instance, see `into_nbt` for the result.
+    pub fn new() -> Encoder {
+        Encoder {
+            stack: Vec::new(),
+            keys: Vec::new()
+        }
+    }
+
+    /// Returns the last encoded value.
+    pub fn into_nbt(mut self) -> EncodeResult<Nbt> {
+        match self.stack.pop() {
+            Some(Some(nbt)) => Ok(nbt),
+            _ => Err(UnsupportedError("missing top-level value".to_string()))
+        }
+    }
+
+    fn push(&mut self, nbt: Nbt) -> EncodeResult<()> {
+        self.stack.push(Some(nbt));
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Nbt> {
+        self.stack.pop().unwrap()
+    }
+
+    fn insert(&mut self, key: String, value: Option<Nbt>) {
+        match (self.stack.last_mut(), value) {
+            (Some(&mut Some(NbtCompound(ref mut c))), Some(value)) => {
+                c.insert(key, value);
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs `f` and turns all the values it pushed into a list.
+    fn collect<F>(&mut self, f: F) -> EncodeResult<List>
+        where F: FnOnce(&mut Encoder) -> EncodeResult<()>
+    {
+        let base = self.stack.len();
+        try!(f(self));
+        let mut tags = Vec::with_capacity(self.stack.len() - base);
+        while self.stack.len() > base {
+            match self.pop() {
+                Some(tag) => tags.push(tag),
+                None => return Err(UnsupportedError("None in a list".to_string()))
+            }
+        }
+        tags.reverse();
+        let tag_type = tags.first().map(|tag| tag.tag_type());
+        List::from_tags(tags).map_err(|other| {
+            MixedListError(format!("tag type {}", tag_type.unwrap()),
+                           format!("tag type {}", other.tag_type()))
+        })
+    }
+}
+
+impl serialize::Encoder for Encoder {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult<()> {
+        Err(UnsupportedError("()".to_string()))
+    }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { self.push(Long(v as i64)) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { self.push(Long(v as i64)) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { self.push(Int(v as i32)) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { self.push(Short(v as i16)) }
+    fn emit_u8 (&mut self, v: u8)  -> EncodeResult<()> { self.push(Byte(v as i8)) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { self.push(Long(v as i64)) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { self.push(Long(v)) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { self.push(Int(v)) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { self.push(Short(v)) }
+    fn emit_i8 (&mut self, v: i8)  -> EncodeResult<()> { self.push(Byte(v)) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> {
+        self.push(Byte(v as i8))
+    }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> { self.push(Double(v)) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> { self.push(Float(v)) }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult<()> {
+        self.push(NbtString(v.to_string()))
+    }
+
+    fn emit_str(&mut self, v: &str) -> EncodeResult<()> {
+        self.push(NbtString(v.to_string()))
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self, name: &str, _id: usize, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        // Mirrors `Decoder::read_enum_variant`: a plain string for unit
+        // variants, a compound with "variant" and "fields" otherwise.
+        if len == 0 {
+            return self.push(NbtString(name.to_string()));
+        }
+        let fields = try!(self.collect(f));
+        let mut c = Compound::new();
+        c.insert("variant".to_string(), NbtString(name.to_string()));
+        c.insert("fields".to_string(), NbtList(fields));
+        self.push(NbtCompound(c))
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self, name: &str, id: usize, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        self.emit_enum_variant(name, id, len, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self, _name: &str, idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, _name: &str, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        try!(self.push(NbtCompound(Compound::new())));
+        f(self)
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        try!(f(self));
+        let value = self.pop();
+        self.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        self.emit_seq(len, f)
+    }
+
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _name: &str, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        self.emit_tuple(len, f)
+    }
+
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        self.emit_tuple_arg(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_option_none(&mut self) -> EncodeResult<()> {
+        // Omitted by `emit_struct_field`, read back as `None` by `read_option`.
+        self.stack.push(None);
+        Ok(())
+    }
+
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        let list = try!(self.collect(f));
+        self.push(NbtList(list))
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        try!(self.push(NbtCompound(Compound::new())));
+        f(self)
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        try!(f(self));
+        match self.pop() {
+            Some(NbtString(key)) => {
+                self.keys.push(key);
+                Ok(())
+            }
+            Some(other) => Err(KeyError(other.to_string())),
+            None => Err(KeyError("None".to_string()))
+        }
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Self) -> EncodeResult<()>
+    {
+        try!(f(self));
+        let value = self.pop();
+        let key = self.keys.pop().unwrap();
+        self.insert(key, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ i32, i64 };
+    use std::iter::repeat;
+    use std::old_io::BufReader;
+
+    use super::*;
+    use super::Nbt::*;
+    use super::List::*;
+    use super::NbtError::{ SizeLimitError, TruncatedError };
+
+    /// A compound with a tag of every type, lists of them included.
+    fn every_tag() -> Nbt {
+        let inner = Compound::new()
+            .with("name", "Steve")
+            .with("ids", vec![1i64, -2, i64::MAX]);
+        Compound::new()
+            .with("byte", -1i8)
+            .with("short", 0x1234i16)
+            .with("int", -0x12345678i32)
+            .with("long", 0x123456789abcdefi64)
+            .with("float", 0.5f32)
+            .with("double", -1.0e100f64)
+            .with("byte_array", vec![0u8, 1, 255])
+            .with("int_array", vec![0i32, -1, i32::MIN])
+            .with("long_array", vec![0i64, -1, i64::MIN])
+            .with("string", "nul \u{0} and \u{1F600}")
+            .with("bytes", ByteList(vec![1, 2, 3]))
+            .with("doubles", DoubleList(vec![0.25, 0.5]))
+            .with("long_arrays", LongArrayList(vec![vec![1, 2], vec![]]))
+            .with("lists", ListList(vec![IntList(vec![1]), List::new()]))
+            .with("compounds", CompoundList(vec![inner.clone(), Compound::new()]))
+            .with("compound", inner)
+            .into_nbt()
+    }
+
+    fn write(nbt: &Nbt, flavour: Flavour) -> Vec<u8> {
+        let mut data = Vec::new();
+        NbtWriter::with_flavour(&mut data, flavour).tag(nbt, "root").unwrap();
+        data
+    }
+
+    fn read(data: &[u8], flavour: Flavour) -> (Nbt, String) {
+        let mut r = BufReader::new(data);
+        let tag = NbtReader::with_flavour(&mut r, flavour).tag().unwrap();
+        assert!(r.eof());
+        tag.unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for &flavour in [Flavour::Java, Flavour::Bedrock, Flavour::Network].iter() {
+            let nbt = every_tag();
+            let data = write(&nbt, flavour);
+            let (read_back, name) = read(data.as_slice(), flavour);
+            assert_eq!(name.as_slice(), "root");
+            assert_eq!(read_back, nbt);
+            assert_eq!(write(&read_back, flavour), data);
+        }
+    }
+
+    #[test]
+    fn hostile_list_lengths() {
+        // Lists of lists, each claiming the most elements allowed, but
+        // without any of them. Reserving that much at each level would
+        // take gigabytes.
+        let mut data = vec![TAG_LIST as u8, 0, 0];
+        for _ in range(0, 32) {
+            data.push_all(&[TAG_LIST as u8, 0x01, 0, 0, 0]);
+        }
+        match Nbt::from_reader(&mut BufReader::new(data.as_slice())) {
+            Err(TruncatedError(_)) => {}
+            other => panic!("expected TruncatedError, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn mutf8() {
+        let cases: &[(&str, &[u8])] = &[
+            ("", b""),
+            ("\u{0}", b"\xc0\x80"),
+            ("a\u{e9}\u{20ac}", b"a\xc3\xa9\xe2\x82\xac"),
+            ("\u{1F600}", b"\xed\xa0\xbd\xed\xb8\x80")
+        ];
+        for &(s, bytes) in cases.iter() {
+            assert_eq!(&*encode_mutf8(s), bytes);
+            assert_eq!(decode_mutf8(bytes).unwrap().as_slice(), s);
+        }
+        // Plain UTF-8 for supplementary characters is accepted too.
+        assert_eq!(decode_mutf8(b"\xf0\x9f\x98\x80").unwrap().as_slice(), "\u{1F600}");
+        // But not when it encodes a BMP character the long way.
+        assert_eq!(decode_mutf8(b"\xf0\x80\x80\x80"), None);
+        assert_eq!(decode_mutf8(b"\xe2\x82"), None);
+    }
+
+    #[test]
+    fn insert_into_empty_lists() {
+        let mut untyped = List::new();
+        assert_eq!(untyped.push(Int(1)), Ok(()));
+        assert_eq!(untyped, IntList(vec![1]));
+        assert_eq!(untyped.push(Byte(2)), Err(Byte(2)));
+
+        let mut typed = ByteList(vec![]);
+        assert_eq!(typed.push(Int(1)), Err(Int(1)));
+        assert_eq!(typed.insert(0, NbtString("a".to_string())), Err(NbtString("a".to_string())));
+        assert_eq!(typed.push(Byte(1)), Ok(()));
+        assert_eq!(typed, ByteList(vec![1]));
+    }
+
+    #[test]
+    fn round_trip_compressed() {
+        let nbt = every_tag();
+        assert_eq!(Nbt::from_gzip(nbt.to_gzip("root").unwrap().as_slice()).unwrap(), nbt);
+        assert_eq!(Nbt::from_zlib(nbt.to_zlib("root").unwrap().as_slice()).unwrap(), nbt);
+    }
+
+    #[test]
+    fn inflate_limit() {
+        let data: Vec<u8> = repeat(0).take(1 << 20).collect();
+        let gzip = deflate_gzip(data.as_slice()).unwrap();
+        let zlib = deflate_zlib(data.as_slice()).unwrap();
+        assert_eq!(inflate_gzip(gzip.as_slice(), 1 << 20).unwrap(), data);
+        assert_eq!(inflate_zlib(zlib.as_slice(), 1 << 20).unwrap(), data);
+        for inflated in [inflate_gzip(gzip.as_slice(), 1 << 16),
+                         inflate_zlib(zlib.as_slice(), 1 << 16)].iter() {
+            match *inflated {
+                Err(SizeLimitError(_)) => {}
+                ref other => panic!("expected SizeLimitError, got {:?}", other)
+            }
+        }
+    }
+}
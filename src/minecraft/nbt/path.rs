@@ -0,0 +1,766 @@
+//! NBT paths, with the syntax of vanilla's `/data` command:
+//! `Data.Player.Pos[1]`, `Inventory[{Slot:0b}].id`, `Level.Sections[]`.
+
+use std::error::Error;
+use std::fmt;
+
+use super::{ Compound, List, Nbt };
+use super::Nbt::*;
+use super::List::*;
+use super::snbt::{ self, SnbtError };
+
+use self::PathNode::*;
+use self::PathError::*;
+
+/// A step of a `NbtPath`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PathNode {
+    /// `{filter}` at the start of a path, matching the root compound.
+    MatchRoot(Compound),
+    /// `name`, a compound entry.
+    Key(String),
+    /// `name{filter}`, a compound entry that matches the filter.
+    MatchKey(String, Compound),
+    /// `[index]`, a list or array element, counted from the end if negative.
+    Index(i32),
+    /// `[]`, every element of a list or array.
+    AllElements,
+    /// `[{filter}]`, every compound element that matches the filter.
+    MatchElements(Compound)
+}
+
+/// A parsed NBT path, see `NbtPath::parse`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NbtPath {
+    nodes: Vec<PathNode>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum PathError {
+    ExpectedError(String, usize),
+    SyntaxError(SnbtError, usize)
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExpectedError(ref what, pos) => write!(f, "expected {} at {}", what, pos),
+            SyntaxError(ref e, pos) => write!(f, "{} in SNBT at {}", e, pos)
+        }
+    }
+}
+
+impl Error for PathError {
+    fn description(&self) -> &str {
+        match *self {
+            ExpectedError(..) => "unexpected character in NBT path",
+            SyntaxError(..) => "invalid SNBT in NBT path"
+        }
+    }
+}
+
+/// A tag found by a path. Elements of typed lists aren't stored as `Nbt`,
+/// so they are found as the compound or list they are, or as a copy.
+#[derive(Clone, Debug)]
+pub enum Target<'a> {
+    Tag(&'a Nbt),
+    Compound(&'a Compound),
+    List(&'a List),
+    Value(Nbt)
+}
+
+impl<'a> Target<'a> {
+    /// The tag itself, unless it's an element of a typed list.
+    pub fn tag(&self) -> Option<&'a Nbt> {
+        match *self { Target::Tag(nbt) => Some(nbt), _ => None }
+    }
+
+    pub fn as_compound(&self) -> Option<&'a Compound> {
+        match *self {
+            Target::Tag(nbt) => match *nbt { NbtCompound(ref c) => Some(c), _ => None },
+            Target::Compound(c) => Some(c),
+            _ => None
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&'a List> {
+        match *self {
+            Target::Tag(nbt) => match *nbt { NbtList(ref l) => Some(l), _ => None },
+            Target::List(l) => Some(l),
+            _ => None
+        }
+    }
+
+    /// Copies the found value into a tag.
+    pub fn to_nbt(&self) -> Nbt {
+        match *self {
+            Target::Tag(nbt) => nbt.clone(),
+            Target::Compound(c) => NbtCompound(c.clone()),
+            Target::List(l) => NbtList(l.clone()),
+            Target::Value(ref v) => v.clone()
+        }
+    }
+
+    fn array(&self) -> Option<&Nbt> {
+        let nbt = match *self {
+            Target::Tag(nbt) => nbt,
+            Target::Value(ref v) => v,
+            _ => return None
+        };
+        match *nbt {
+            ByteArray(_) | IntArray(_) | LongArray(_) => Some(nbt),
+            _ => None
+        }
+    }
+}
+
+/// A tag found by a path for editing. Elements of typed lists other than
+/// compounds and lists can't be borrowed, use `NbtPath::set` for those.
+#[derive(Debug)]
+pub enum TargetMut<'a> {
+    Tag(&'a mut Nbt),
+    Compound(&'a mut Compound),
+    List(&'a mut List)
+}
+
+impl<'a> TargetMut<'a> {
+    /// The tag itself, unless it's an element of a typed list.
+    pub fn into_tag(self) -> Option<&'a mut Nbt> {
+        match self { TargetMut::Tag(nbt) => Some(nbt), _ => None }
+    }
+
+    pub fn into_compound(self) -> Option<&'a mut Compound> {
+        match self {
+            TargetMut::Tag(nbt) => match *nbt { NbtCompound(ref mut c) => Some(c), _ => None },
+            TargetMut::Compound(c) => Some(c),
+            TargetMut::List(_) => None
+        }
+    }
+
+    pub fn into_list(self) -> Option<&'a mut List> {
+        match self {
+            TargetMut::Tag(nbt) => match *nbt { NbtList(ref mut l) => Some(l), _ => None },
+            TargetMut::List(l) => Some(l),
+            TargetMut::Compound(_) => None
+        }
+    }
+
+    fn as_compound(&self) -> Option<&Compound> {
+        match *self {
+            TargetMut::Tag(ref nbt) => match **nbt { NbtCompound(ref c) => Some(c), _ => None },
+            TargetMut::Compound(ref c) => Some(&**c),
+            TargetMut::List(_) => None
+        }
+    }
+}
+
+/// Checks whether `value` contains `filter`, like vanilla does: compounds
+/// may have more entries, and lists have to contain every filter element.
+pub fn matches(filter: &Nbt, value: &Nbt) -> bool {
+    match (filter, value) {
+        (&NbtCompound(ref f), &NbtCompound(ref c)) => matches_compound(f, c),
+        (&NbtList(ref f), &NbtList(ref l)) => {
+            if f.is_empty() {
+                return l.is_empty();
+            }
+            let tags = l.to_tags();
+            f.to_tags().iter().all(|f| tags.iter().any(|v| matches(f, v)))
+        }
+        _ => filter == value
+    }
+}
+
+fn matches_compound(filter: &Compound, c: &Compound) -> bool {
+    filter.iter().all(|(k, f)| c.get(k.as_slice()).map_or(false, |v| matches(f, v)))
+}
+
+fn matches_tag(filter: &Compound, nbt: &Nbt) -> bool {
+    match *nbt { NbtCompound(ref c) => matches_compound(filter, c), _ => false }
+}
+
+fn resolve_index(index: i32, len: usize) -> Option<usize> {
+    let i = if index < 0 { len as i64 + index as i64 } else { index as i64 };
+    if i >= 0 && i < len as i64 { Some(i as usize) } else { None }
+}
+
+fn array_len(nbt: &Nbt) -> usize {
+    match *nbt {
+        ByteArray(ref v) => v.len(),
+        IntArray(ref v) => v.len(),
+        LongArray(ref v) => v.len(),
+        _ => 0
+    }
+}
+
+fn array_get(nbt: &Nbt, i: usize) -> Nbt {
+    match *nbt {
+        ByteArray(ref v) => Byte(v[i] as i8),
+        IntArray(ref v) => Int(v[i]),
+        LongArray(ref v) => Long(v[i]),
+        _ => unreachable!()
+    }
+}
+
+fn array_set(nbt: &mut Nbt, i: usize, value: &Nbt) -> bool {
+    match (nbt, value) {
+        (&mut ByteArray(ref mut v), &Byte(x)) => v[i] = x as u8,
+        (&mut IntArray(ref mut v), &Int(x)) => v[i] = x,
+        (&mut LongArray(ref mut v), &Long(x)) => v[i] = x,
+        _ => return false
+    }
+    true
+}
+
+fn array_remove(nbt: &mut Nbt, i: usize) {
+    match *nbt {
+        ByteArray(ref mut v) => { v.remove(i); }
+        IntArray(ref mut v) => { v.remove(i); }
+        LongArray(ref mut v) => { v.remove(i); }
+        _ => {}
+    }
+}
+
+/// The indices of the elements selected by an element node.
+fn selected(node: &PathNode, len: usize, compounds: Option<&Vec<Compound>>) -> Vec<usize> {
+    match *node {
+        Index(i) => resolve_index(i, len).into_iter().collect(),
+        AllElements => range(0, len).collect(),
+        MatchElements(ref f) => match compounds {
+            Some(v) => v.iter().enumerate()
+                .filter(|&(_, c)| matches_compound(f, c))
+                .map(|(i, _)| i).collect(),
+            None => Vec::new()
+        },
+        _ => Vec::new()
+    }
+}
+
+fn step<'a>(node: &PathNode, target: Target<'a>, out: &mut Vec<Target<'a>>) {
+    match *node {
+        MatchRoot(ref f) => {
+            if target.as_compound().map_or(false, |c| matches_compound(f, c)) {
+                out.push(target);
+            }
+        }
+        Key(ref k) => {
+            match target.as_compound().and_then(|c| c.get(k.as_slice())) {
+                Some(v) => out.push(Target::Tag(v)),
+                None => {}
+            }
+        }
+        MatchKey(ref k, ref f) => {
+            match target.as_compound().and_then(|c| c.get(k.as_slice())) {
+                Some(v) if matches_tag(f, v) => out.push(Target::Tag(v)),
+                _ => {}
+            }
+        }
+        _ => {
+            match target.as_list() {
+                Some(&CompoundList(ref v)) => {
+                    for i in selected(node, v.len(), Some(v)).into_iter() {
+                        out.push(Target::Compound(&v[i]));
+                    }
+                }
+                Some(&ListList(ref v)) => {
+                    for i in selected(node, v.len(), None).into_iter() {
+                        out.push(Target::List(&v[i]));
+                    }
+                }
+                Some(l) => {
+                    for i in selected(node, l.len(), None).into_iter() {
+                        out.push(Target::Value(l.get(i).unwrap()));
+                    }
+                }
+                None => match target.array() {
+                    Some(array) => {
+                        for i in selected(node, array_len(array), None).into_iter() {
+                            out.push(Target::Value(array_get(array, i)));
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+/// With `create`, a missing key is added as an empty compound first.
+fn step_mut<'a>(node: &PathNode, target: TargetMut<'a>, create: bool,
+                out: &mut Vec<TargetMut<'a>>) {
+    match *node {
+        MatchRoot(ref f) => {
+            if target.as_compound().map_or(false, |c| matches_compound(f, c)) {
+                out.push(target);
+            }
+        }
+        Key(ref k) => {
+            let c = match target.into_compound() { Some(c) => c, None => return };
+            if create && !c.contains_key(k.as_slice()) {
+                c.insert(k.clone(), NbtCompound(Compound::new()));
+            }
+            match c.get_mut(k.as_slice()) {
+                Some(v) => out.push(TargetMut::Tag(v)),
+                None => {}
+            }
+        }
+        MatchKey(ref k, ref f) => {
+            match target.into_compound().and_then(|c| c.get_mut(k.as_slice())) {
+                Some(v) => {
+                    if matches_tag(f, v) {
+                        out.push(TargetMut::Tag(v));
+                    }
+                }
+                None => {}
+            }
+        }
+        _ => {
+            match target.into_list() {
+                Some(&mut CompoundList(ref mut v)) => {
+                    let selected = selected(node, v.len(), Some(&*v));
+                    for (i, c) in v.iter_mut().enumerate() {
+                        if selected.contains(&i) {
+                            out.push(TargetMut::Compound(c));
+                        }
+                    }
+                }
+                Some(&mut ListList(ref mut v)) => {
+                    let selected = selected(node, v.len(), None);
+                    for (i, l) in v.iter_mut().enumerate() {
+                        if selected.contains(&i) {
+                            out.push(TargetMut::List(l));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn is_key_char(c: char) -> bool {
+    match c {
+        ' ' | '"' | '\'' | '[' | ']' | '.' | '{' | '}' => false,
+        _ => true
+    }
+}
+
+impl NbtPath {
+    /// Parses a path like `Data.Player.Pos[1]`.
+    pub fn parse(s: &str) -> Result<NbtPath, PathError> {
+        let mut nodes = Vec::new();
+        let mut pos = 0;
+        if s.starts_with("{") {
+            let (filter, len) = try!(snbt::compound_prefix(s).map_err(|e| SyntaxError(e, 0)));
+            nodes.push(MatchRoot(filter));
+            pos = len;
+        }
+        while pos < s.len() {
+            let rest = &s[pos..];
+            if rest.starts_with("[") {
+                let (node, len) = try!(NbtPath::parse_element(rest, pos));
+                nodes.push(node);
+                pos += len;
+            } else {
+                if !nodes.is_empty() {
+                    if !rest.starts_with(".") {
+                        return Err(ExpectedError("'.' or '['".to_string(), pos));
+                    }
+                    pos += 1;
+                }
+                let (node, len) = try!(NbtPath::parse_key(&s[pos..], pos));
+                nodes.push(node);
+                pos += len;
+            }
+        }
+        if nodes.is_empty() {
+            return Err(ExpectedError("path".to_string(), 0));
+        }
+        Ok(NbtPath { nodes: nodes })
+    }
+
+    fn parse_key(s: &str, pos: usize) -> Result<(PathNode, usize), PathError> {
+        let (key, mut len) = if s.starts_with("\"") || s.starts_with("'") {
+            try!(snbt::quoted_prefix(s).map_err(|e| SyntaxError(e, pos)))
+        } else {
+            let len = s.find(|c: char| !is_key_char(c)).unwrap_or(s.len());
+            if len == 0 {
+                return Err(ExpectedError("key".to_string(), pos));
+            }
+            (s[..len].to_string(), len)
+        };
+        if s[len..].starts_with("{") {
+            let (filter, filter_len) = try!(snbt::compound_prefix(&s[len..])
+                .map_err(|e| SyntaxError(e, pos + len)));
+            len += filter_len;
+            Ok((MatchKey(key, filter), len))
+        } else {
+            Ok((Key(key), len))
+        }
+    }
+
+    fn parse_element(s: &str, pos: usize) -> Result<(PathNode, usize), PathError> {
+        let inner = &s[1..];
+        let (node, len) = if inner.starts_with("]") {
+            (AllElements, 0)
+        } else if inner.starts_with("{") {
+            let (filter, len) = try!(snbt::compound_prefix(inner)
+                .map_err(|e| SyntaxError(e, pos + 1)));
+            (MatchElements(filter), len)
+        } else {
+            let len = inner.find(']').unwrap_or(inner.len());
+            match inner[..len].parse() {
+                Ok(i) => (Index(i), len),
+                Err(_) => return Err(ExpectedError("index".to_string(), pos + 1))
+            }
+        };
+        if !inner[len..].starts_with("]") {
+            return Err(ExpectedError("']'".to_string(), pos + 1 + len));
+        }
+        Ok((node, len + 2))
+    }
+
+    pub fn from_nodes(nodes: Vec<PathNode>) -> NbtPath {
+        NbtPath { nodes: nodes }
+    }
+
+    pub fn nodes(&self) -> &[PathNode] {
+        self.nodes.as_slice()
+    }
+
+    pub fn push(&mut self, node: PathNode) {
+        self.nodes.push(node);
+    }
+
+    pub fn pop(&mut self) -> Option<PathNode> {
+        self.nodes.pop()
+    }
+
+    /// Finds every tag matching this path.
+    pub fn get_all<'a>(&self, root: &'a Nbt) -> Vec<Target<'a>> {
+        let mut targets = vec![Target::Tag(root)];
+        for node in self.nodes.iter() {
+            let mut next = Vec::new();
+            for target in targets.into_iter() {
+                step(node, target, &mut next);
+            }
+            targets = next;
+        }
+        targets
+    }
+
+    /// Finds the first tag matching this path.
+    pub fn get<'a>(&self, root: &'a Nbt) -> Option<Target<'a>> {
+        self.get_all(root).into_iter().next()
+    }
+
+    /// Follows the first `len` nodes. With `create`, missing keys followed
+    /// by another key are added as empty compounds, like vanilla does; an
+    /// empty compound couldn't match a filter or an element anyway.
+    fn walk_mut<'a>(&self, len: usize, root: &'a mut Nbt, create: bool) -> Vec<TargetMut<'a>> {
+        let mut targets = vec![TargetMut::Tag(root)];
+        for (i, node) in self.nodes[..len].iter().enumerate() {
+            let create = create && match self.nodes.get(i + 1) {
+                Some(&Key(_)) => true,
+                _ => false
+            };
+            let mut next = Vec::new();
+            for target in targets.into_iter() {
+                step_mut(node, target, create, &mut next);
+            }
+            targets = next;
+        }
+        targets
+    }
+
+    /// Finds every tag matching this path for editing, see `TargetMut`.
+    pub fn get_all_mut<'a>(&self, root: &'a mut Nbt) -> Vec<TargetMut<'a>> {
+        self.walk_mut(self.nodes.len(), root, false)
+    }
+
+    /// Sets every tag matching this path to `value`, creating missing keys
+    /// along the way as compounds. Returns how many tags were set; list elements
+    /// of a different type than `value` are left alone.
+    pub fn set(&self, root: &mut Nbt, value: Nbt) -> usize {
+        let n = self.nodes.len();
+        if n == 0 {
+            *root = value;
+            return 1;
+        }
+        let last = &self.nodes[n - 1];
+        let mut count = 0;
+        for parent in self.walk_mut(n - 1, root, true).into_iter() {
+            count += match *last {
+                MatchRoot(_) => 0,
+                Key(ref k) => match parent.into_compound() {
+                    Some(c) => {
+                        c.insert(k.clone(), value.clone());
+                        1
+                    }
+                    None => 0
+                },
+                MatchKey(ref k, ref f) => match parent.into_compound() {
+                    Some(c) => match c.get_mut(k.as_slice()) {
+                        Some(v) => {
+                            if matches_tag(f, v) {
+                                *v = value.clone();
+                                1
+                            } else {
+                                0
+                            }
+                        }
+                        None => 0
+                    },
+                    None => 0
+                },
+                _ => match parent {
+                    TargetMut::Tag(nbt) => match *nbt {
+                        NbtList(ref mut l) => set_elements(last, l, &value),
+                        ref mut array => {
+                            let selected = selected(last, array_len(array), None);
+                            selected.into_iter().filter(|&i| array_set(array, i, &value)).count()
+                        }
+                    },
+                    TargetMut::List(l) => set_elements(last, l, &value),
+                    TargetMut::Compound(_) => 0
+                }
+            };
+        }
+        count
+    }
+
+    /// Removes every tag matching this path, returning how many there were.
+    pub fn remove(&self, root: &mut Nbt) -> usize {
+        let n = self.nodes.len();
+        if n == 0 {
+            return 0;
+        }
+        let last = &self.nodes[n - 1];
+        let mut count = 0;
+        for parent in self.walk_mut(n - 1, root, false).into_iter() {
+            count += match *last {
+                MatchRoot(_) => 0,
+                Key(ref k) => match parent.into_compound() {
+                    Some(c) => c.remove(k.as_slice()).map_or(0, |_| 1),
+                    None => 0
+                },
+                MatchKey(ref k, ref f) => match parent.into_compound() {
+                    Some(c) => {
+                        if c.get(k.as_slice()).map_or(false, |v| matches_tag(f, v)) {
+                            c.remove(k.as_slice());
+                            1
+                        } else {
+                            0
+                        }
+                    }
+                    None => 0
+                },
+                _ => match parent {
+                    TargetMut::Tag(nbt) => match *nbt {
+                        NbtList(ref mut l) => remove_elements(last, l),
+                        ref mut array => {
+                            let selected = selected(last, array_len(array), None);
+                            for &i in selected.iter().rev() {
+                                array_remove(array, i);
+                            }
+                            selected.len()
+                        }
+                    },
+                    TargetMut::List(l) => remove_elements(last, l),
+                    TargetMut::Compound(_) => 0
+                }
+            };
+        }
+        count
+    }
+}
+
+fn list_selection(node: &PathNode, list: &List) -> Vec<usize> {
+    match *list {
+        CompoundList(ref v) => selected(node, v.len(), Some(v)),
+        _ => selected(node, list.len(), None)
+    }
+}
+
+fn set_elements(node: &PathNode, list: &mut List, value: &Nbt) -> usize {
+    let selected = list_selection(node, list);
+    selected.into_iter().filter(|&i| list.set(i, value.clone()).is_ok()).count()
+}
+
+fn remove_elements(node: &PathNode, list: &mut List) -> usize {
+    let selected = list_selection(node, list);
+    for &i in selected.iter().rev() {
+        list.remove(i);
+    }
+    selected.len()
+}
+
+fn write_key(f: &mut fmt::Formatter, key: &str) -> fmt::Result {
+    if !key.is_empty() && key.chars().all(is_key_char) {
+        write!(f, "{}", key)
+    } else {
+        snbt::write_string(f, key)
+    }
+}
+
+impl fmt::Display for NbtPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, node) in self.nodes.iter().enumerate() {
+            match *node {
+                MatchRoot(ref filter) => try!(snbt::write_compound(f, filter)),
+                Key(ref k) | MatchKey(ref k, _) => {
+                    if i > 0 {
+                        try!(write!(f, "."));
+                    }
+                    try!(write_key(f, k.as_slice()));
+                }
+                Index(index) => try!(write!(f, "[{}]", index)),
+                AllElements => try!(write!(f, "[]")),
+                MatchElements(ref filter) => {
+                    try!(write!(f, "["));
+                    try!(snbt::write_compound(f, filter));
+                    try!(write!(f, "]"));
+                }
+            }
+            match *node {
+                MatchKey(_, ref filter) => try!(snbt::write_compound(f, filter)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Nbt {
+    /// Finds the first tag matching a path like `Data.Player.Pos`,
+    /// without panicking on missing tags like indexing does.
+    pub fn lookup(&self, path: &str) -> Result<Option<Target>, PathError> {
+        NbtPath::parse(path).map(|path| path.get(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NbtPath;
+    use super::PathNode::*;
+    use super::PathError::*;
+    use super::super::{ Compound, Nbt };
+    use super::super::Nbt::*;
+
+    fn path(s: &str) -> NbtPath {
+        NbtPath::parse(s).unwrap()
+    }
+
+    fn player() -> Nbt {
+        Nbt::from_snbt(concat!(
+            "{Data:{Player:{Pos:[0.5d,64.0d,-3.5d],Scores:[I;1,2,3],Inventory:[",
+            "{Slot:0b,id:\"stone\"},{Slot:1b,id:\"dirt\"},{Slot:2b,id:\"stone\"}]}}}"
+        )).unwrap()
+    }
+
+    fn found(path_str: &str, nbt: &Nbt) -> Vec<Nbt> {
+        path(path_str).get_all(nbt).iter().map(|t| t.to_nbt()).collect()
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(NbtPath::parse(""), Err(ExpectedError("path".to_string(), 0)));
+        assert_eq!(NbtPath::parse("a."), Err(ExpectedError("key".to_string(), 2)));
+        assert_eq!(NbtPath::parse("a b"), Err(ExpectedError("'.' or '['".to_string(), 1)));
+        assert_eq!(NbtPath::parse("a[x]"), Err(ExpectedError("index".to_string(), 2)));
+        assert_eq!(NbtPath::parse("a[1"), Err(ExpectedError("']'".to_string(), 3)));
+        match NbtPath::parse("{id:") {
+            Err(SyntaxError(_, 0)) => {}
+            r => panic!("expected a syntax error, got {:?}", r)
+        }
+        match NbtPath::parse("a{b:}") {
+            Err(SyntaxError(_, 1)) => {}
+            r => panic!("expected a syntax error, got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let p = path("{id:\"x\"}.Items[{Slot:0b}].tag{a:1}[-1][]");
+        assert_eq!(p.nodes(), [
+            MatchRoot(Compound::new().with("id", "x")),
+            Key("Items".to_string()),
+            MatchElements(Compound::new().with("Slot", 0i8)),
+            MatchKey("tag".to_string(), Compound::new().with("a", 1i32)),
+            Index(-1),
+            AllElements
+        ].as_slice());
+        assert_eq!(path(p.to_string().as_slice()), p);
+
+        let quoted = path("\"a b\".c");
+        assert_eq!(quoted.nodes(), [Key("a b".to_string()), Key("c".to_string())].as_slice());
+        assert_eq!(path(quoted.to_string().as_slice()), quoted);
+    }
+
+    #[test]
+    fn compound_filters() {
+        let nbt = player();
+        assert_eq!(found("Data.Player.Inventory[{id:\"stone\"}].Slot", &nbt),
+                   vec![Byte(0), Byte(2)]);
+        assert_eq!(found("Data.Player.Inventory[{id:\"gold\"}]", &nbt), vec![]);
+        assert!(path("{Data:{Player:{}}}.Data").get(&nbt).is_some());
+        assert!(path("{Data:{Missing:1b}}.Data").get(&nbt).is_none());
+        // List filters only need to be contained in the list.
+        assert!(path("Data.Player{Pos:[64.0d]}.Pos").get(&nbt).is_some());
+        assert!(path("Data.Player{Pos:[1.0d]}.Pos").get(&nbt).is_none());
+    }
+
+    #[test]
+    fn indexes() {
+        let nbt = player();
+        assert_eq!(found("Data.Player.Pos[1]", &nbt), vec![Double(64.0)]);
+        assert_eq!(found("Data.Player.Pos[-1]", &nbt), vec![Double(-3.5)]);
+        assert_eq!(found("Data.Player.Pos[-3]", &nbt), vec![Double(0.5)]);
+        assert_eq!(found("Data.Player.Pos[3]", &nbt), vec![]);
+        assert_eq!(found("Data.Player.Pos[-4]", &nbt), vec![]);
+        assert_eq!(found("Data.Player.Scores[-1]", &nbt), vec![Int(3)]);
+        assert_eq!(found("Data.Player.Inventory[1].id", &nbt), vec![NbtString("dirt".to_string())]);
+    }
+
+    #[test]
+    fn wildcards() {
+        let nbt = player();
+        assert_eq!(found("Data.Player.Scores[]", &nbt), vec![Int(1), Int(2), Int(3)]);
+        assert_eq!(found("Data.Player.Inventory[].Slot", &nbt), vec![Byte(0), Byte(1), Byte(2)]);
+        assert_eq!(found("Data.Player[]", &nbt), vec![]);
+    }
+
+    #[test]
+    fn set_creates_intermediate_keys() {
+        let mut nbt = NbtCompound(Compound::new());
+        assert_eq!(path("a.b.c").set(&mut nbt, Int(1)), 1);
+        assert_eq!(nbt.lookup("a.b.c").unwrap().unwrap().to_nbt(), Int(1));
+
+        // Nothing is created for elements, an empty compound has none.
+        assert_eq!(path("x[0]").set(&mut nbt, Int(1)), 0);
+        assert!(nbt.lookup("x").unwrap().is_none());
+    }
+
+    #[test]
+    fn set_elements() {
+        let mut nbt = player();
+        assert_eq!(path("Data.Player.Pos[-1]").set(&mut nbt, Double(0.0)), 1);
+        assert_eq!(found("Data.Player.Pos[]", &nbt), vec![Double(0.5), Double(64.0), Double(0.0)]);
+        assert_eq!(path("Data.Player.Pos[0]").set(&mut nbt, Int(0)), 0);
+        assert_eq!(path("Data.Player.Scores[]").set(&mut nbt, Int(7)), 3);
+        assert_eq!(found("Data.Player.Scores[]", &nbt), vec![Int(7), Int(7), Int(7)]);
+        assert_eq!(path("Data.Player.Inventory[].Count").set(&mut nbt, Byte(1)), 3);
+        assert_eq!(found("Data.Player.Inventory[{Count:1b}]", &nbt).len(), 3);
+    }
+
+    #[test]
+    fn remove() {
+        let mut nbt = player();
+        assert_eq!(path("Data.Player.Inventory[{id:\"stone\"}]").remove(&mut nbt), 2);
+        assert_eq!(found("Data.Player.Inventory[].id", &nbt), vec![NbtString("dirt".to_string())]);
+        assert_eq!(path("Data.Player.Scores[0]").remove(&mut nbt), 1);
+        assert_eq!(found("Data.Player.Scores[]", &nbt), vec![Int(2), Int(3)]);
+        assert_eq!(path("Data.Player.Missing").remove(&mut nbt), 0);
+        assert_eq!(path("Data.Player").remove(&mut nbt), 1);
+        assert!(nbt.lookup("Data.Player").unwrap().is_none());
+    }
+}
@@ -0,0 +1,566 @@
+//! Stringified NBT, the text format used by commands (`{Count:1b,id:"stone"}`).
+
+use std::ascii::AsciiExt;
+use std::error::Error;
+use std::fmt;
+
+use super::{ Compound, List, Nbt };
+use super::Nbt::*;
+use super::List::*;
+
+use self::SnbtError::*;
+
+/// An error encountered while parsing SNBT, with the byte offset into the input.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SnbtError {
+    ExpectedError(String, usize),
+    UnexpectedEndError,
+    InvalidEscapeError(char, usize),
+    MixedListError(usize),
+    TrailingDataError(usize),
+    /// Compounds and lists nested deeper than vanilla allows.
+    DepthLimitError(usize)
+}
+
+impl fmt::Display for SnbtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExpectedError(ref what, pos) => write!(f, "expected {} at {}", what, pos),
+            UnexpectedEndError => write!(f, "unexpected end of input"),
+            InvalidEscapeError(c, pos) => write!(f, "invalid escape '\\{}' at {}", c, pos),
+            MixedListError(pos) => write!(f, "list elements of different types at {}", pos),
+            TrailingDataError(pos) => write!(f, "unexpected data after value at {}", pos),
+            DepthLimitError(pos) => write!(f, "nested too deeply at {}", pos)
+        }
+    }
+}
+
+impl Error for SnbtError {
+    fn description(&self) -> &str {
+        match *self {
+            ExpectedError(..) => "unexpected character",
+            UnexpectedEndError => "unexpected end of input",
+            InvalidEscapeError(..) => "invalid escape sequence",
+            MixedListError(..) => "list elements of different types",
+            TrailingDataError(..) => "unexpected data after value",
+            DepthLimitError(..) => "nested too deeply"
+        }
+    }
+}
+
+pub type SnbtResult<T> = Result<T, SnbtError>;
+
+impl Nbt {
+    /// Parses a value from SNBT, e.g. `{Pos:[0.5d,64.0d,0.5d],Data:[I;1,2]}`.
+    pub fn from_snbt(s: &str) -> SnbtResult<Nbt> {
+        let mut parser = Parser::new(s);
+        let value = try!(parser.value());
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(TrailingDataError(parser.pos))
+        }
+    }
+
+    /// Prints this value as canonical SNBT: no whitespace, compound
+    /// keys sorted, and every number carrying its type suffix.
+    ///
+    /// SNBT has no syntax for NaN and infinite floats, which are printed
+    /// as `NaNf`, `inff` and the like, and read back as strings like
+    /// vanilla does, so they don't survive a round trip.
+    pub fn to_snbt(&self) -> String {
+        format!("{}", Snbt(self))
+    }
+}
+
+/// Parses a compound at the start of `s`, returning it along with
+/// the number of bytes it took up.
+pub fn compound_prefix(s: &str) -> SnbtResult<(Compound, usize)> {
+    let mut parser = Parser::new(s);
+    let c = try!(parser.compound());
+    Ok((c, parser.pos))
+}
+
+/// Parses a quoted string at the start of `s`, like `compound_prefix`.
+pub fn quoted_prefix(s: &str) -> SnbtResult<(String, usize)> {
+    let mut parser = Parser::new(s);
+    let quote = try!(parser.next());
+    let string = try!(parser.quoted(quote));
+    Ok((string, parser.pos))
+}
+
+/// Displays a value as canonical SNBT, see `Nbt::to_snbt`.
+pub struct Snbt<'a>(pub &'a Nbt);
+
+impl<'a> fmt::Display for Snbt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self.0)
+    }
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    match c {
+        '0'...'9' | 'a'...'z' | 'A'...'Z' | '_' | '-' | '.' | '+' => true,
+        _ => false
+    }
+}
+
+pub fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    // Like vanilla, prefer double quotes unless that needs more escapes.
+    let quote = if s.contains("\"") && !s.contains("'") { '\'' } else { '"' };
+    try!(write!(f, "{}", quote));
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            try!(write!(f, "\\"));
+        }
+        try!(write!(f, "{}", c));
+    }
+    write!(f, "{}", quote)
+}
+
+fn write_key(f: &mut fmt::Formatter, key: &str) -> fmt::Result {
+    if !key.is_empty() && key.chars().all(is_unquoted_char) {
+        write!(f, "{}", key)
+    } else {
+        write_string(f, key)
+    }
+}
+
+/// Writes a float so that it always reads back as a float, unless it
+/// isn't finite, see `Nbt::to_snbt`.
+fn write_float<T: fmt::Display>(f: &mut fmt::Formatter, x: T, suffix: &str) -> fmt::Result {
+    let s = x.to_string();
+    if s.chars().all(|c| c == '-' || c.is_digit(10)) {
+        write!(f, "{}.0{}", s, suffix)
+    } else {
+        write!(f, "{}{}", s, suffix)
+    }
+}
+
+fn write_seq<T, F>(f: &mut fmt::Formatter, prefix: &str, v: &[T], mut write: F) -> fmt::Result
+    where F: FnMut(&mut fmt::Formatter, &T) -> fmt::Result
+{
+    try!(write!(f, "[{}", prefix));
+    for (i, x) in v.iter().enumerate() {
+        if i > 0 {
+            try!(write!(f, ","));
+        }
+        try!(write(f, x));
+    }
+    write!(f, "]")
+}
+
+pub fn write_compound(f: &mut fmt::Formatter, c: &Compound) -> fmt::Result {
+    let mut entries: Vec<(&String, &Nbt)> = c.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    try!(write!(f, "{{"));
+    for (i, &(key, value)) in entries.iter().enumerate() {
+        if i > 0 {
+            try!(write!(f, ","));
+        }
+        try!(write_key(f, key.as_slice()));
+        try!(write!(f, ":"));
+        try!(write_value(f, value));
+    }
+    write!(f, "}}")
+}
+
+fn write_list(f: &mut fmt::Formatter, list: &List) -> fmt::Result {
+    match *list {
+        ByteList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write!(f, "{}b", x)),
+        ShortList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write!(f, "{}s", x)),
+        IntList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write!(f, "{}", x)),
+        LongList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write!(f, "{}L", x)),
+        FloatList(ref v) => write_seq(f, "", v.as_slice(), |f, &x| write_float(f, x, "f")),
+        DoubleList(ref v) => write_seq(f, "", v.as_slice(), |f, &x| write_float(f, x, "d")),
+        ByteArrayList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write_byte_array(f, x.as_slice())),
+        IntArrayList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write_int_array(f, x.as_slice())),
+        LongArrayList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write_long_array(f, x.as_slice())),
+        StringList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write_string(f, x.as_slice())),
+        ListList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write_list(f, x)),
+        CompoundList(ref v) => write_seq(f, "", v.as_slice(), |f, x| write_compound(f, x))
+    }
+}
+
+fn write_byte_array(f: &mut fmt::Formatter, v: &[u8]) -> fmt::Result {
+    write_seq(f, "B;", v, |f, &x| write!(f, "{}b", x as i8))
+}
+
+fn write_int_array(f: &mut fmt::Formatter, v: &[i32]) -> fmt::Result {
+    write_seq(f, "I;", v, |f, x| write!(f, "{}", x))
+}
+
+fn write_long_array(f: &mut fmt::Formatter, v: &[i64]) -> fmt::Result {
+    write_seq(f, "L;", v, |f, x| write!(f, "{}L", x))
+}
+
+fn write_value(f: &mut fmt::Formatter, nbt: &Nbt) -> fmt::Result {
+    match *nbt {
+        Byte(x) => write!(f, "{}b", x),
+        Short(x) => write!(f, "{}s", x),
+        Int(x) => write!(f, "{}", x),
+        Long(x) => write!(f, "{}L", x),
+        Float(x) => write_float(f, x, "f"),
+        Double(x) => write_float(f, x, "d"),
+        ByteArray(ref v) => write_byte_array(f, v.as_slice()),
+        IntArray(ref v) => write_int_array(f, v.as_slice()),
+        LongArray(ref v) => write_long_array(f, v.as_slice()),
+        NbtString(ref s) => write_string(f, s.as_slice()),
+        NbtList(ref l) => write_list(f, l),
+        NbtCompound(ref c) => write_compound(f, c)
+    }
+}
+
+/// How deeply compounds and lists may be nested, like vanilla.
+const MAX_DEPTH: usize = 512;
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// The compounds and lists the parser is in.
+    depth: usize
+}
+
+/// Checks for an optionally signed integer without leading zeros.
+fn is_integer(s: &str) -> bool {
+    let digits = s.trim_left_matches(|c| c == '-' || c == '+');
+    s.len() - digits.len() <= 1
+        && !digits.is_empty()
+        && digits.chars().all(|c| c.is_digit(10))
+        && (digits == "0" || !digits.starts_with("0"))
+}
+
+/// Checks for a decimal number, which must contain a dot unless `suffixed`.
+fn is_decimal(s: &str, suffixed: bool) -> bool {
+    let (mantissa, exponent) = match s.find(|c| c == 'e' || c == 'E') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None)
+    };
+    let exponent_ok = match exponent {
+        Some(e) => {
+            let digits = e.trim_left_matches(|c| c == '-' || c == '+');
+            e.len() - digits.len() <= 1
+                && !digits.is_empty()
+                && digits.chars().all(|c| c.is_digit(10))
+        }
+        None => true
+    };
+    let digits = mantissa.trim_left_matches(|c| c == '-' || c == '+');
+    let dots = digits.chars().filter(|&c| c == '.').count();
+    exponent_ok
+        && mantissa.len() - digits.len() <= 1
+        && digits.chars().any(|c| c.is_digit(10))
+        && digits.chars().all(|c| c == '.' || c.is_digit(10))
+        && (dots == 1 || dots == 0 && suffixed)
+}
+
+/// Interprets an unquoted token like vanilla does: numbers that don't
+/// fit their type, and anything else, are strings.
+fn unquoted_value(token: &str) -> Nbt {
+    let lower = token.to_ascii_lowercase();
+    match lower.as_slice() {
+        "true" => return Byte(1),
+        "false" => return Byte(0),
+        _ => {}
+    }
+    if !token.is_empty() {
+        let body = &token[..token.len() - 1];
+        let parsed = match lower.chars().rev().next().unwrap() {
+            'b' if is_integer(body) => body.parse().ok().map(Byte),
+            's' if is_integer(body) => body.parse().ok().map(Short),
+            'l' if is_integer(body) => body.parse().ok().map(Long),
+            'f' if is_decimal(body, true) => body.parse().ok().map(Float),
+            'd' if is_decimal(body, true) => body.parse().ok().map(Double),
+            _ if is_integer(token) => token.parse().ok().map(Int),
+            _ if is_decimal(token, false) => token.parse().ok().map(Double),
+            _ => None
+        };
+        match parsed {
+            Some(value) => return value,
+            None => {}
+        }
+    }
+    NbtString(token.to_string())
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Parser<'a> {
+        Parser { src: src, pos: 0, depth: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> SnbtResult<char> {
+        match self.peek() {
+            Some(c) => {
+                self.pos += c.len_utf8();
+                Ok(c)
+            }
+            None => Err(UnexpectedEndError)
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.pos += c.len_utf8(),
+                _ => break
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> SnbtResult<()> {
+        self.skip_whitespace();
+        let pos = self.pos;
+        match try!(self.next()) {
+            c if c == expected => Ok(()),
+            _ => Err(ExpectedError(format!("'{}'", expected), pos))
+        }
+    }
+
+    /// Consumes `c` if it's the next non-whitespace character.
+    fn accept(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn quoted(&mut self, quote: char) -> SnbtResult<String> {
+        let mut s = String::new();
+        loop {
+            let pos = self.pos;
+            match try!(self.next()) {
+                '\\' => match try!(self.next()) {
+                    c if c == quote || c == '\\' => s.push(c),
+                    c => return Err(InvalidEscapeError(c, pos))
+                },
+                c if c == quote => return Ok(s),
+                c => s.push(c)
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> SnbtResult<&'a str> {
+        let start = self.pos;
+        while self.peek().map_or(false, is_unquoted_char) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return match self.peek() {
+                Some(_) => Err(ExpectedError("value".to_string(), start)),
+                None => Err(UnexpectedEndError)
+            };
+        }
+        Ok(&self.src[start..self.pos])
+    }
+
+    fn key(&mut self) -> SnbtResult<String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(q) if q == '"' || q == '\'' => {
+                self.pos += 1;
+                self.quoted(q)
+            }
+            _ => self.unquoted().map(|s| s.to_string())
+        }
+    }
+
+    /// Parses a compound or list, one level deeper.
+    fn nested<T, F>(&mut self, parse: F) -> SnbtResult<T>
+        where F: FnOnce(&mut Parser<'a>) -> SnbtResult<T>
+    {
+        if self.depth >= MAX_DEPTH {
+            return Err(DepthLimitError(self.pos));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn value(&mut self) -> SnbtResult<Nbt> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(|p| p.compound()).map(NbtCompound),
+            Some('[') => self.nested(|p| p.list()),
+            Some(q) if q == '"' || q == '\'' => {
+                self.pos += 1;
+                self.quoted(q).map(NbtString)
+            }
+            _ => self.unquoted().map(unquoted_value)
+        }
+    }
+
+    fn compound(&mut self) -> SnbtResult<Compound> {
+        let mut c = Compound::new();
+        try!(self.expect('{'));
+        if self.accept('}') {
+            return Ok(c);
+        }
+        loop {
+            let key = try!(self.key());
+            try!(self.expect(':'));
+            let value = try!(self.value());
+            c.insert(key, value);
+            if !self.accept(',') {
+                break;
+            }
+        }
+        try!(self.expect('}'));
+        Ok(c)
+    }
+
+    /// Parses the elements of a list or array, up to the closing `]`.
+    fn elements(&mut self) -> SnbtResult<Vec<(Nbt, usize)>> {
+        let mut elements = Vec::new();
+        if self.accept(']') {
+            return Ok(elements);
+        }
+        loop {
+            self.skip_whitespace();
+            let pos = self.pos;
+            elements.push((try!(self.value()), pos));
+            if !self.accept(',') {
+                break;
+            }
+        }
+        try!(self.expect(']'));
+        Ok(elements)
+    }
+
+    fn list(&mut self) -> SnbtResult<Nbt> {
+        try!(self.expect('['));
+        self.skip_whitespace();
+        let rest = &self.src[self.pos..];
+        let array_type = match rest.chars().next() {
+            Some(t) if (t == 'B' || t == 'I' || t == 'L') && rest[1..].starts_with(";") => {
+                self.pos += 2;
+                Some(t)
+            }
+            _ => None
+        };
+
+        let elements = try!(self.elements());
+        match array_type {
+            None => {
+                match elements.first() {
+                    Some(&(ref first, _)) => {
+                        let tag_type = first.tag_type();
+                        match elements.iter().find(|&&(ref tag, _)| tag.tag_type() != tag_type) {
+                            Some(&(_, pos)) => return Err(MixedListError(pos)),
+                            None => {}
+                        }
+                    }
+                    None => {}
+                }
+                let tags = elements.into_iter().map(|(tag, _)| tag).collect();
+                Ok(NbtList(List::from_tags(tags).unwrap()))
+            }
+            Some(array_type) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for &(ref tag, pos) in elements.iter() {
+                    // Any integer is accepted, as long as it fits.
+                    let x = match *tag {
+                        Byte(x) => x as i64,
+                        Short(x) => x as i64,
+                        Int(x) => x as i64,
+                        Long(x) => x,
+                        _ => return Err(ExpectedError("integer".to_string(), pos))
+                    };
+                    let fits = match array_type {
+                        'B' => x as i8 as i64 == x,
+                        'I' => x as i32 as i64 == x,
+                        _ => true
+                    };
+                    if !fits {
+                        return Err(ExpectedError(format!("{} array element", array_type), pos));
+                    }
+                    values.push(x);
+                }
+                Ok(match array_type {
+                    'B' => ByteArray(values.into_iter().map(|x| x as i8 as u8).collect()),
+                    'I' => IntArray(values.into_iter().map(|x| x as i32).collect()),
+                    _ => LongArray(values)
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::repeat;
+
+    use super::super::{ Compound, IntoNbt, List, Nbt };
+    use super::super::List::*;
+    use super::SnbtError::*;
+
+    fn round_trip(nbt: &Nbt) {
+        let snbt = nbt.to_snbt();
+        let parsed = Nbt::from_snbt(snbt.as_slice()).unwrap();
+        assert_eq!(&parsed, nbt);
+        assert_eq!(parsed.to_snbt(), snbt);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(&Compound::new()
+            .with("byte", -1i8)
+            .with("short", 300i16)
+            .with("int", 0i32)
+            .with("long", -1234567890123i64)
+            .with("float", 0.5f32)
+            .with("double", 3.0f64)
+            .with("bytes", vec![0u8, 128, 255])
+            .with("ints", vec![1i32, -1])
+            .with("longs", vec![1i64 << 40])
+            .with("empty_bytes", Vec::<u8>::new())
+            .with("quote's \"key\"", "back\\slash and \"quotes\"")
+            .with("", "'single'")
+            .with("list", ListList(vec![ByteList(vec![1, 2]), List::new()]))
+            .with("compounds", CompoundList(vec![Compound::new().with("a", "b")]))
+            .into_nbt());
+    }
+
+    #[test]
+    fn parses_vanilla_syntax() {
+        let nbt = Nbt::from_snbt(
+            "{ \"quoted key\" : 'it\\'s', Count:1b, Damage:2S, big:3l, x:1.5F, y:2,\
+               z:2.5, flag:true, Data:[B; 1b, 2], Ids:[I;], word:stone }").unwrap();
+        let expected = Compound::new()
+            .with("quoted key", "it's")
+            .with("Count", 1i8)
+            .with("Damage", 2i16)
+            .with("big", 3i64)
+            .with("x", 1.5f32)
+            .with("y", 2i32)
+            .with("z", 2.5f64)
+            .with("flag", true)
+            .with("Data", vec![1u8, 2])
+            .with("Ids", Vec::<i32>::new())
+            .with("word", "stone")
+            .into_nbt();
+        assert_eq!(nbt, expected);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Nbt::from_snbt("{a:1"), Err(UnexpectedEndError));
+        assert_eq!(Nbt::from_snbt("[1,2b]"), Err(MixedListError(3)));
+        assert_eq!(Nbt::from_snbt("[B;300]"), Err(ExpectedError("B array element".to_string(), 3)));
+        assert_eq!(Nbt::from_snbt("\"\\n\""), Err(InvalidEscapeError('n', 1)));
+        assert_eq!(Nbt::from_snbt("1 2"), Err(TrailingDataError(2)));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let deep: String = repeat('[').take(100000).collect();
+        assert_eq!(Nbt::from_snbt(deep.as_slice()), Err(DepthLimitError(512)));
+    }
+}
@@ -0,0 +1,178 @@
+//! Streaming NBT reading, for picking a few tags out of a large tree.
+
+use std::cmp::min;
+
+use super::{ Flavour, Nbt, NbtPos, NbtReader, NbtResult };
+use super::NbtError::*;
+use super::{ TAG_END, TAG_BYTE, TAG_SHORT, TAG_INT, TAG_LONG, TAG_FLOAT, TAG_DOUBLE,
+             TAG_BYTE_ARRAY, TAG_STRING, TAG_LIST, TAG_COMPOUND, TAG_INT_ARRAY,
+             TAG_LONG_ARRAY };
+
+/// What to do with a tag announced to a `NbtVisitor`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Visit {
+    /// Discard the payload without decoding or allocating it.
+    Skip,
+    /// Decode the whole tag and pass it to `NbtVisitor::value`.
+    Read,
+    /// Announce the elements of a compound or list one by one, followed
+    /// by `NbtVisitor::end`. Other tags are treated as with `Read`.
+    Enter
+}
+
+/// Receives the tags of a NBT stream, see `NbtReader::visit`.
+pub trait NbtVisitor {
+    /// Announces a tag, named `name` inside compounds and `""` inside lists.
+    fn tag(&mut self, tag_type: i8, name: &str) -> Visit;
+
+    /// A tag for which `tag` returned `Read`.
+    fn value(&mut self, _name: &str, _value: Nbt) {}
+
+    /// The end of a compound or list for which `tag` returned `Enter`.
+    fn end(&mut self) {}
+}
+
+impl<'a, R: Reader> NbtReader<'a, R> {
+    /// The payload size of tags that don't have a length prefix,
+    /// unless they are varints.
+    fn fixed_size(&self, tag_type: i8) -> Option<usize> {
+        match tag_type {
+            TAG_BYTE => Some(1),
+            TAG_SHORT => Some(2),
+            TAG_FLOAT => Some(4),
+            TAG_DOUBLE => Some(8),
+            TAG_INT if self.flavour != Flavour::Network => Some(4),
+            TAG_LONG if self.flavour != Flavour::Network => Some(8),
+            _ => None
+        }
+    }
+
+    /// Reads the next tag like `tag`, but reports it to `visitor` instead
+    /// of building it. Returns `false` at the end of a compound.
+    pub fn visit<V: NbtVisitor>(&mut self, visitor: &mut V) -> NbtResult<bool> {
+        match try!(self.i8()) {
+            TAG_END => Ok(false),
+            tag_type if tag_type < TAG_END || tag_type > TAG_LONG_ARRAY => {
+                Err(self.unknown_tag(tag_type))
+            }
+            tag_type => {
+                let name = try!(self.string());
+                match self.visit_payload(tag_type, name.as_slice(), visitor) {
+                    Ok(()) => Ok(true),
+                    Err(e) => Err(e.within(name))
+                }
+            }
+        }
+    }
+
+    fn visit_payload<V: NbtVisitor>(&mut self, tag_type: i8, name: &str, visitor: &mut V)
+                                    -> NbtResult<()> {
+        match (visitor.tag(tag_type, name), tag_type) {
+            (Visit::Skip, _) => self.skip(tag_type),
+            (Visit::Enter, TAG_COMPOUND) => self.nested(|r| {
+                while try!(r.visit(visitor)) {}
+                visitor.end();
+                Ok(())
+            }),
+            (Visit::Enter, TAG_LIST) => self.nested(|r| {
+                let (element_type, len) = try!(r.list_header());
+                for i in range(0, len) {
+                    match r.visit_payload(element_type, "", visitor) {
+                        Ok(()) => {}
+                        Err(e) => return Err(e.within(format!("[{}]", i)))
+                    }
+                }
+                visitor.end();
+                Ok(())
+            }),
+            _ => {
+                let value = try!(self.payload(tag_type));
+                visitor.value(name, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the element type and length of a list.
+    fn list_header(&mut self) -> NbtResult<(i8, usize)> {
+        let element_type = try!(self.i8());
+        if element_type < TAG_END || element_type > TAG_LONG_ARRAY {
+            return Err(self.unknown_tag(element_type));
+        }
+        let start = self.offset() - 1;
+        let len = try!(self.len());
+        if element_type == TAG_END && len != 0 {
+            return Err(UnknownTagError(TAG_END, NbtPos::at(start)));
+        }
+        Ok((element_type, len))
+    }
+
+    fn skip_bytes(&mut self, mut len: usize) -> NbtResult<()> {
+        let mut buf = [0u8; 4096];
+        while len > 0 {
+            let n = min(len, buf.len());
+            try!(self.read(n as u64, |r| r.read_at_least(n, &mut buf[..n])));
+            len -= n;
+        }
+        Ok(())
+    }
+
+    /// Skips the payload of a tag of a known, valid type.
+    fn skip(&mut self, tag_type: i8) -> NbtResult<()> {
+        match self.fixed_size(tag_type) {
+            Some(size) => return self.skip_bytes(size),
+            None => {}
+        }
+        match tag_type {
+            TAG_INT => self.i32().map(|_| ()),
+            TAG_LONG => self.i64().map(|_| ()),
+            TAG_BYTE_ARRAY => {
+                let len = try!(self.len());
+                self.skip_bytes(len)
+            }
+            TAG_INT_ARRAY | TAG_LONG_ARRAY => {
+                let element_type = if tag_type == TAG_INT_ARRAY { TAG_INT } else { TAG_LONG };
+                let len = try!(self.len());
+                match self.fixed_size(element_type) {
+                    Some(size) => self.skip_bytes(len * size),
+                    None => {
+                        for _ in range(0, len) {
+                            try!(self.skip(element_type));
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            TAG_STRING => {
+                let len = try!(self.string_len());
+                self.skip_bytes(len)
+            }
+            TAG_LIST => self.nested(|r| {
+                let (element_type, len) = try!(r.list_header());
+                match r.fixed_size(element_type) {
+                    Some(size) => r.skip_bytes(len * size),
+                    None => {
+                        for _ in range(0, len) {
+                            try!(r.skip(element_type));
+                        }
+                        Ok(())
+                    }
+                }
+            }),
+            _ => self.nested(|r| {
+                loop {
+                    match try!(r.i8()) {
+                        TAG_END => return Ok(()),
+                        t if t < TAG_END || t > TAG_LONG_ARRAY => {
+                            return Err(r.unknown_tag(t))
+                        }
+                        t => {
+                            try!(r.skip(TAG_STRING));
+                            try!(r.skip(t));
+                        }
+                    }
+                }
+            })
+        }
+    }
+}
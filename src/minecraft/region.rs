@@ -1,8 +1,15 @@
-use std::cell::RefCell;
-use std::old_io::{ File, FileStat, IoResult };
+use std::borrow::Cow;
+use std::cmp::{ max, min };
+use std::error::Error;
+use std::fmt;
+use std::iter::repeat;
+use std::old_io::{ fs, BufReader, File, FileAccess, FileMode, FileStat, IoError, IoErrorKind,
+                   IoResult, SeekStyle };
+use std::old_io::fs::PathExtensions;
 use std::os;
 
 use gfx;
+use time;
 
 use array::*;
 use chunk::{
@@ -14,10 +21,219 @@ use chunk::{
     LightLevel,
     SIZE
 };
-use minecraft::nbt::Nbt;
+
+use minecraft::lz4;
+use minecraft::nbt::{ self, Nbt, NbtError, NbtLimits, NbtRef };
+
+use self::RegionError::*;
+
+const SECTOR_SIZE: usize = 4096;
+
+pub const COMPRESSION_GZIP: u8 = 1;
+pub const COMPRESSION_ZLIB: u8 = 2;
+pub const COMPRESSION_NONE: u8 = 3;
+pub const COMPRESSION_LZ4: u8 = 4;
+/// A codec named by a string before the data, that only mods know.
+pub const COMPRESSION_CUSTOM: u8 = 127;
+/// Set on the compression type of chunks stored in an external file.
+pub const COMPRESSION_EXTERNAL: u8 = 0x80;
+
+/// Decompressed chunk data, uncompressed data being used in place.
+enum ChunkData<'a> {
+    Decompressed(Vec<u8>),
+    Stored(&'a [u8])
+}
+
+impl<'a> ChunkData<'a> {
+    fn as_slice(&self) -> &[u8] {
+        match *self {
+            ChunkData::Decompressed(ref data) => data.as_slice(),
+            ChunkData::Stored(data) => data
+        }
+    }
+}
+
+/// Decompresses chunk data stored as `compression`, into no more than
+/// `max_len` bytes.
+fn decompress(compression: u8, data: &[u8], max_len: u64) -> RegionResult<ChunkData> {
+    match compression {
+        COMPRESSION_GZIP => {
+            nbt::inflate_gzip(data, max_len).map(ChunkData::Decompressed).map_err(ChunkNbtError)
+        }
+        COMPRESSION_ZLIB => {
+            nbt::inflate_zlib(data, max_len).map(ChunkData::Decompressed).map_err(ChunkNbtError)
+        }
+        COMPRESSION_NONE => Ok(ChunkData::Stored(data)),
+        COMPRESSION_LZ4 => {
+            lz4::decompress(data, max_len).map(ChunkData::Decompressed)
+                .ok_or(DecompressError(compression))
+        }
+        COMPRESSION_CUSTOM => {
+            let mut reader = BufReader::new(data);
+            let name = reader.read_be_u16()
+                .and_then(|len| reader.read_exact(len as usize))
+                .map(|name| String::from_utf8_lossy(name.as_slice()).into_owned())
+                .unwrap_or(String::new());
+            Err(CustomCompressionError(name))
+        }
+        c => Err(CompressionError(c))
+    }
+}
+
+/// Compresses chunk data as `compression`, for the types that can be written.
+fn compress(compression: u8, data: &[u8]) -> IoResult<Vec<u8>> {
+    match compression {
+        COMPRESSION_GZIP => nbt::deflate_gzip(data),
+        COMPRESSION_ZLIB => nbt::deflate_zlib(data),
+        COMPRESSION_NONE => Ok(data.to_vec()),
+        COMPRESSION_LZ4 => Ok(lz4::compress(data)),
+        c => Err(IoError {
+            kind: IoErrorKind::InvalidInput,
+            desc: "compression type can't be written",
+            detail: Some(format!("compression type {}", c))
+        })
+    }
+}
+
+/// Parses the region coordinates out of an `r.<x>.<z>.mca` file name.
+pub fn region_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = match path.filename_str() {
+        Some(name) => name,
+        None => return None
+    };
+    let parts: Vec<&str> = name.split('.').collect();
+    if parts.len() != 4 || parts[0] != "r" || parts[3] != "mca" {
+        return None;
+    }
+    match (parts[1].parse(), parts[2].parse()) {
+        (Ok(x), Ok(z)) => Some((x, z)),
+        _ => None
+    }
+}
+
+/// The file a chunk too large for its region is stored in, next to the
+/// region file, which has to be named after its coordinates.
+fn external_path(dir: &Path, coords: Option<(i32, i32)>, x: u8, z: u8) -> IoResult<Path> {
+    match coords {
+        Some((region_x, region_z)) => Ok(dir.join(format!("c.{}.{}.mcc",
+            region_x * 32 + x as i32, region_z * 32 + z as i32))),
+        None => Err(IoError {
+            kind: IoErrorKind::InvalidInput,
+            desc: "region file name has no coordinates",
+            detail: None
+        })
+    }
+}
+
+/// Where and how a chunk is stored in a region file.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ChunkInfo {
+    /// The chunk's position within the region.
+    pub x: u8,
+    pub z: u8,
+    /// The first sector of the chunk's data, and how many it takes up.
+    pub sector: usize,
+    pub sector_count: usize,
+    /// The length of the compressed data, in the region or its external file.
+    pub compressed_len: usize,
+    /// The compression type, with `COMPRESSION_EXTERNAL` set if the data
+    /// is in an external file.
+    pub compression: u8,
+    /// When the chunk was last written, in seconds since the epoch.
+    pub timestamp: u32
+}
 
 pub struct Region {
     mmap: os::MemoryMap,
+    /// The directory holding the region file and its external chunks.
+    dir: Path,
+    coords: Option<(i32, i32)>,
+    /// Bounds on the chunks read, which come from files that could be
+    /// corrupt or malicious.
+    limits: NbtLimits
+}
+
+/// Why a chunk couldn't be read from a region file.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RegionError {
+    /// The chunk hasn't been generated.
+    MissingChunkError,
+    /// The region file exists but couldn't be opened.
+    OpenError(IoError),
+    /// The chunk's first sector and sector count point outside the file.
+    SectorRangeError(usize, usize),
+    /// The chunk's length header doesn't fit its sectors.
+    ChunkLengthError(usize),
+    /// The chunk uses an unknown compression type.
+    CompressionError(u8),
+    /// The chunk uses a custom compression type, by the name of its codec.
+    CustomCompressionError(String),
+    /// The chunk's external `.mcc` file couldn't be read.
+    ExternalChunkError(IoError),
+    /// The chunk's data couldn't be decompressed as its compression type.
+    DecompressError(u8),
+    ChunkNbtError(NbtError),
+    /// A tag the chunk needs is missing, or has the wrong type or size.
+    MissingFieldError(String)
+}
+
+pub type RegionResult<T> = Result<T, RegionError>;
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SectorRangeError(start, count) => {
+                write!(f, "sectors {}..{} out of range", start, start + count)
+            }
+            ChunkLengthError(len) => write!(f, "invalid chunk length {}", len),
+            CompressionError(c) => write!(f, "unknown compression type {}", c),
+            CustomCompressionError(ref name) => write!(f, "custom compression '{}'", name),
+            DecompressError(c) => write!(f, "couldn't decompress compression type {}", c),
+            ExternalChunkError(ref e) => write!(f, "couldn't read external chunk file: {}", e),
+            OpenError(ref e) => write!(f, "couldn't open region file: {}", e),
+            ChunkNbtError(ref e) => write!(f, "invalid chunk NBT: {}", e),
+            MissingFieldError(ref path) => write!(f, "missing or invalid field {}", path),
+            ref e => write!(f, "{}", e.description())
+        }
+    }
+}
+
+impl Error for RegionError {
+    fn description(&self) -> &str {
+        match *self {
+            MissingChunkError => "chunk not generated",
+            OpenError(..) => "couldn't open region file",
+            SectorRangeError(..) => "chunk sectors out of range",
+            ChunkLengthError(..) => "invalid chunk length",
+            CompressionError(..) => "unknown compression type",
+            CustomCompressionError(..) => "unsupported custom compression",
+            ExternalChunkError(..) => "couldn't read external chunk file",
+            DecompressError(..) => "couldn't decompress chunk",
+            ChunkNbtError(..) => "invalid chunk NBT",
+            MissingFieldError(..) => "missing or invalid field"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ChunkNbtError(ref e) => Some(e),
+            ExternalChunkError(ref e) | OpenError(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// A tag that has to be in a chunk, at `path` followed by `name`.
+fn field<'a>(nbt: NbtRef<'a>, path: &str, name: &str) -> RegionResult<NbtRef<'a>> {
+    nbt.get(name).ok_or_else(|| MissingFieldError(format!("{}{}", path, name)))
+}
+
+fn byte_array<'a>(nbt: NbtRef<'a>, path: &str, name: &str, len: usize)
+                  -> RegionResult<&'a [u8]> {
+    match nbt.get(name).and_then(|x| x.as_byte_array()) {
+        Some(array) if array.len() == len => Ok(array),
+        _ => Err(MissingFieldError(format!("{}{}", path, name)))
+    }
 }
 
 fn array_16x16x16<T, F>(mut f: F) -> [[[T; SIZE]; SIZE]; SIZE]
@@ -32,6 +248,13 @@ fn array_16x16x16<T, F>(mut f: F) -> [[[T; SIZE]; SIZE]; SIZE]
 
 impl Region {
     pub fn open(filename: &Path) -> IoResult<Region> {
+        Region::open_with_limits(filename, NbtLimits::default())
+    }
+
+    /// Opens a region file, reading chunks within other limits than the
+    /// default ones. `limits.max_bytes` bounds chunks once decompressed,
+    /// as well as the external files of oversized chunks.
+    pub fn open_with_limits(filename: &Path, limits: NbtLimits) -> IoResult<Region> {
         #[cfg(not(windows))]
         fn map_fd(file: &File) -> os::MapOption {
             use std::os::unix::AsRawFd;
@@ -51,10 +274,20 @@ impl Region {
             map_fd(&file),
             os::MapOption::MapReadable
         ];
-        let res = Region {
-            mmap: os::MemoryMap::new(min_len, options).unwrap()
+        let mmap = match os::MemoryMap::new(min_len, options) {
+            Ok(mmap) => mmap,
+            Err(e) => return Err(IoError {
+                kind: IoErrorKind::OtherIoError,
+                desc: "could not map region file",
+                detail: Some(e.to_string())
+            })
         };
-        Ok(res)
+        Ok(Region {
+            mmap: mmap,
+            dir: filename.dir_path(),
+            coords: region_coords(filename),
+            limits: limits
+        })
     }
 
     fn as_slice<'a>(&'a self) -> &'a [u8] {
@@ -68,44 +301,173 @@ impl Region {
         unsafe { mem::transmute(slice) }
     }
 
-    pub fn get_chunk_column<R: gfx::Resources>(&self, x: u8, z: u8)
-                            -> Option<ChunkColumn<R>> {
-        let locations = &self.as_slice()[..4096];
-        let i = 4 * ((x % 32) as usize + (z % 32) as usize * 32);
+    /// The first sector and sector count of a chunk, from the location table.
+    pub fn location(&self, x: u8, z: u8) -> RegionResult<(usize, usize)> {
+        let region = self.as_slice();
+        if region.len() < SECTOR_SIZE {
+            return Err(SectorRangeError(0, 1));
+        }
+        let locations = &region[..SECTOR_SIZE];
+        let i = 4 * chunk_index(x, z);
         let start = ((locations[i] as usize) << 16)
                   | ((locations[i + 1] as usize) << 8)
                   | (locations[i + 2] as usize);
         let num = locations[i + 3] as usize;
-        if start == 0 || num == 0 { return None; }
-        let sectors = &self.as_slice()[start * 4096 .. (start + num) * 4096];
-        let len = ((sectors[0] as usize) << 24)
-                | ((sectors[1] as usize) << 16)
-                | ((sectors[2] as usize) << 8)
-                | (sectors[3] as usize);
-        let nbt = match sectors[4] {
-            1 => Nbt::from_gzip(&sectors[5 .. 4 + len]),
-            2 => Nbt::from_zlib(&sectors[5 .. 4 + len]),
-            c => panic!("unknown region chunk compression method {}", c)
+        if start == 0 || num == 0 { return Err(MissingChunkError); }
+        Ok((start, num))
+    }
+
+    /// The number of sectors in the file, counting a partial last one.
+    pub fn sector_count(&self) -> usize {
+        (self.as_slice().len() + SECTOR_SIZE - 1) / SECTOR_SIZE
+    }
+
+    /// When a chunk was last written, in seconds since the epoch.
+    pub fn timestamp(&self, x: u8, z: u8) -> u32 {
+        let region = self.as_slice();
+        let i = SECTOR_SIZE + 4 * chunk_index(x, z);
+        if region.len() < i + 4 { 0 } else { be_u32(&region[i..]) }
+    }
+
+    /// The compression type, as stored, and the data in the region of a chunk.
+    fn chunk_sectors(&self, x: u8, z: u8) -> RegionResult<(u8, &[u8])> {
+        let region = self.as_slice();
+        let (start, num) = try!(self.location(x, z));
+        // The last sector may be cut short, as long as the data fits.
+        if start < 2 || start * SECTOR_SIZE + 5 > region.len() {
+            return Err(SectorRangeError(start, num));
+        }
+        let sectors = &region[start * SECTOR_SIZE
+                              .. min((start + num) * SECTOR_SIZE, region.len())];
+        let len = be_u32(sectors) as usize;
+        if len == 0 || len > sectors.len() - 4 {
+            return Err(ChunkLengthError(len));
+        }
+        Ok((sectors[4], &sectors[5 .. 4 + len]))
+    }
+
+    /// Where and how a chunk is stored, without reading its data.
+    pub fn chunk_info(&self, x: u8, z: u8) -> RegionResult<ChunkInfo> {
+        let (sector, sector_count) = try!(self.location(x, z));
+        let (compression, data) = try!(self.chunk_sectors(x, z));
+        let compressed_len = if compression & COMPRESSION_EXTERNAL == 0 {
+            data.len()
+        } else {
+            try!(external_path(&self.dir, self.coords, x, z)
+                .and_then(|path| fs::stat(&path))
+                .map_err(ExternalChunkError)).size as usize
         };
+        Ok(ChunkInfo {
+            x: x,
+            z: z,
+            sector: sector,
+            sector_count: sector_count,
+            compressed_len: compressed_len,
+            compression: compression,
+            timestamp: self.timestamp(x, z)
+        })
+    }
 
-        let mut c = nbt.unwrap().into_compound().unwrap();
-        let mut level = c.remove("Level").unwrap().into_compound().unwrap();
+    /// Lists the chunks in the region, in the order of the location table.
+    /// Chunks whose header can't be read, as `chunk_info` would report,
+    /// are left out.
+    pub fn chunks(&self) -> Vec<ChunkInfo> {
         let mut chunks = Vec::new();
-        for chunk in level.remove("Sections")
-            .unwrap().into_compound_list().unwrap().into_iter() {
-
-            let y = chunk.get("Y")
-                .unwrap().as_byte().unwrap();
-            let blocks = chunk.get("Blocks")
-                .unwrap().as_bytearray().unwrap();
-            let blocks_top = chunk.get("Add")
-                .and_then(|x| x.as_bytearray());
-            let blocks_data = chunk.get("Data")
-                .unwrap().as_bytearray().unwrap();
-            let block_light = chunk.get("BlockLight")
-                .unwrap().as_bytearray().unwrap();
-            let sky_light = chunk.get("SkyLight")
-                .unwrap().as_bytearray().unwrap();
+        for z in range(0, 32) {
+            for x in range(0, 32) {
+                match self.chunk_info(x, z) {
+                    Ok(info) => chunks.push(info),
+                    Err(_) => {}
+                }
+            }
+        }
+        chunks
+    }
+
+    /// The compression type and still compressed data of a chunk, read
+    /// from its external file if it's too large for the region.
+    pub fn raw_chunk(&self, x: u8, z: u8) -> RegionResult<(u8, Cow<[u8]>)> {
+        let (compression, data) = try!(self.chunk_sectors(x, z));
+        if compression & COMPRESSION_EXTERNAL == 0 {
+            return Ok((compression, Cow::Borrowed(data)));
+        }
+        let max_bytes = self.limits.max_bytes;
+        let data = try!(external_path(&self.dir, self.coords, x, z)
+            .and_then(|path| {
+                let mut file = try!(File::open(&path));
+                let size = try!(file.stat()).size;
+                if size > max_bytes {
+                    return Err(IoError {
+                        kind: IoErrorKind::InvalidInput,
+                        desc: "external chunk file too large",
+                        detail: Some(format!("{} bytes, at most {} allowed", size, max_bytes))
+                    });
+                }
+                file.read_to_end()
+            })
+            .map_err(ExternalChunkError));
+        Ok((compression & !COMPRESSION_EXTERNAL, Cow::Owned(data)))
+    }
+
+    /// Decompresses the NBT data of a chunk.
+    fn chunk_data(&self, x: u8, z: u8) -> RegionResult<ChunkData> {
+        let max_bytes = self.limits.max_bytes;
+        match try!(self.raw_chunk(x, z)) {
+            (compression, Cow::Borrowed(data)) => decompress(compression, data, max_bytes),
+            // Data read from an external file can't be used in place.
+            (COMPRESSION_NONE, Cow::Owned(data)) => Ok(ChunkData::Decompressed(data)),
+            (compression, Cow::Owned(data)) => {
+                match try!(decompress(compression, data.as_slice(), max_bytes)) {
+                    ChunkData::Decompressed(bytes) => Ok(ChunkData::Decompressed(bytes)),
+                    ChunkData::Stored(_) => unreachable!()
+                }
+            }
+        }
+    }
+
+    /// Reads the whole NBT tree of a chunk.
+    pub fn get_chunk_nbt(&self, x: u8, z: u8) -> RegionResult<Nbt> {
+        let data = try!(self.chunk_data(x, z));
+        Nbt::from_reader_with_limits(&mut BufReader::new(data.as_slice()), self.limits)
+            .map_err(ChunkNbtError)
+    }
+
+    pub fn get_chunk_column<R: gfx::Resources>(&self, x: u8, z: u8)
+                            -> RegionResult<ChunkColumn<R>> {
+        let (chunks, biomes) = try!(self.get_chunk_blocks(x, z));
+        Ok(ChunkColumn::new(chunks, biomes))
+    }
+
+    /// Reads the chunks and biomes of a column, without the buffers of
+    /// a `ChunkColumn`, so that it can be done on another thread.
+    pub fn get_chunk_blocks(&self, x: u8, z: u8)
+                            -> RegionResult<(Vec<Chunk>, [[BiomeId; SIZE]; SIZE])> {
+        let data = try!(self.chunk_data(x, z));
+
+        // Section arrays are used in place, without copying them out.
+        let root = try!(NbtRef::from_slice_with_limits(data.as_slice(), self.limits)
+            .map_err(ChunkNbtError));
+        let level = try!(field(root, "", "Level"));
+        let sections = match try!(field(level, "Level.", "Sections")).as_list() {
+            Some(sections) => sections,
+            None => return Err(MissingFieldError("Level.Sections".to_string()))
+        };
+        let mut chunks = Vec::new();
+        for (i, chunk) in sections.iter().enumerate() {
+            let path = format!("Level.Sections[{}].", i);
+            let path = path.as_slice();
+            let y = match try!(field(chunk, path, "Y")).as_byte() {
+                Some(y) if y >= 0 && (y as usize) < SIZE => y,
+                _ => return Err(MissingFieldError(format!("{}Y", path)))
+            };
+            let blocks = try!(byte_array(chunk, path, "Blocks", 4096));
+            let blocks_top = match chunk.get("Add") {
+                Some(_) => Some(try!(byte_array(chunk, path, "Add", 2048))),
+                None => None
+            };
+            let blocks_data = try!(byte_array(chunk, path, "Data", 2048));
+            let block_light = try!(byte_array(chunk, path, "BlockLight", 2048));
+            let sky_light = try!(byte_array(chunk, path, "SkyLight", 2048));
 
             let chunk = Chunk {
                 blocks: array_16x16x16(|x, y, z| {
@@ -139,16 +501,353 @@ impl Region {
             }
             chunks[y as usize] = chunk;
         }
-        let biomes = level.get("Biomes")
-            .unwrap().as_bytearray().unwrap();
-        Some(ChunkColumn {
-            chunks: chunks,
-            buffers: Array::from_fn(|_| RefCell::new(None)),
-            biomes: Array::from_fn(|z| -> [BiomeId; SIZE] Array::from_fn(|x| {
-                BiomeId {
-                    value: biomes[z * SIZE + x]
+        let biomes = try!(byte_array(level, "Level.", "Biomes", 256));
+        Ok((chunks, Array::from_fn(|z| -> [BiomeId; SIZE] Array::from_fn(|x| {
+            BiomeId {
+                value: biomes[z * SIZE + x]
+            }
+        }))))
+    }
+}
+
+fn be_u32(d: &[u8]) -> u32 {
+    ((d[0] as u32) << 24) | ((d[1] as u32) << 16) | ((d[2] as u32) << 8) | (d[3] as u32)
+}
+
+fn chunk_index(x: u8, z: u8) -> usize {
+    (x % 32) as usize + (z % 32) as usize * 32
+}
+
+/// Writes chunks into a region file, keeping its location and
+/// timestamp tables up to date.
+pub struct RegionWriter {
+    file: File,
+    /// The directory holding the region file and its external chunks.
+    dir: Path,
+    coords: Option<(i32, i32)>,
+    /// The first sector and sector count of each chunk, as `start << 8 | count`.
+    locations: [u32; 1024],
+    /// When each chunk was last written, in seconds since the epoch.
+    timestamps: [u32; 1024],
+    /// Whether each sector of the file is taken.
+    used: Vec<bool>
+}
+
+impl RegionWriter {
+    /// Opens a region file for writing, creating it if it doesn't exist.
+    /// Files too short to hold the header are refused rather than
+    /// overwritten, since they're most likely truncated regions.
+    pub fn open(filename: &Path) -> IoResult<RegionWriter> {
+        let mut file = try!(File::open_mode(filename, FileMode::Open, FileAccess::ReadWrite));
+        let len = try!(file.stat()).size;
+        let mut header = [0u8; 2 * SECTOR_SIZE];
+        if len == 0 {
+            try!(file.write_all(&header));
+        } else if len >= header.len() as u64 {
+            try!(file.read_at_least(header.len(), &mut header));
+        } else {
+            return Err(IoError {
+                kind: IoErrorKind::InvalidInput,
+                desc: "truncated region header",
+                detail: Some(format!("{} bytes, the header takes {}", len, header.len()))
+            });
+        }
+
+        let mut locations = [0u32; 1024];
+        let mut timestamps = [0u32; 1024];
+        for i in range(0, 1024) {
+            locations[i] = be_u32(&header[i * 4..]);
+            timestamps[i] = be_u32(&header[SECTOR_SIZE + i * 4..]);
+        }
+
+        let sectors = (len as usize + SECTOR_SIZE - 1) / SECTOR_SIZE;
+        let mut used: Vec<bool> = repeat(false).take(max(sectors, 2)).collect();
+        used[0] = true;
+        used[1] = true;
+        for &location in locations.iter() {
+            let start = (location >> 8) as usize;
+            let end = start + (location & 0xff) as usize;
+            if start >= 2 && end <= used.len() {
+                for sector in used[start..end].iter_mut() {
+                    *sector = true;
                 }
-            }))
+            }
+        }
+
+        Ok(RegionWriter {
+            file: file,
+            dir: filename.dir_path(),
+            coords: region_coords(filename),
+            locations: locations,
+            timestamps: timestamps,
+            used: used
         })
     }
+
+    /// Marks the sectors of a chunk as free, leaving its table entries.
+    fn free(&mut self, i: usize) {
+        let start = (self.locations[i] >> 8) as usize;
+        let end = start + (self.locations[i] & 0xff) as usize;
+        if start >= 2 && end <= self.used.len() {
+            for sector in self.used[start..end].iter_mut() {
+                *sector = false;
+            }
+        }
+    }
+
+    /// Finds the first `count` free sectors in a row, growing the file
+    /// if there are none.
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut run = 0;
+        let mut start = 0;
+        for i in range(2, self.used.len()) {
+            if self.used[i] {
+                run = 0;
+            } else {
+                run += 1;
+                if run == count {
+                    start = i + 1 - count;
+                    break;
+                }
+            }
+        }
+        if run < count {
+            // Any free sectors at the end of the file are followed by new ones.
+            start = self.used.len() - run;
+        }
+        let len = max(self.used.len(), start + count);
+        self.used.resize(len, false);
+        for sector in self.used[start..start + count].iter_mut() {
+            *sector = true;
+        }
+        start
+    }
+
+    fn write_header(&mut self, i: usize) -> IoResult<()> {
+        try!(self.file.seek((i * 4) as i64, SeekStyle::SeekSet));
+        try!(self.file.write_be_u32(self.locations[i]));
+        try!(self.file.seek((SECTOR_SIZE + i * 4) as i64, SeekStyle::SeekSet));
+        self.file.write_be_u32(self.timestamps[i])
+    }
+
+    /// Deletes the external file of a chunk, if it has one.
+    fn remove_external(&mut self, x: u8, z: u8) -> IoResult<()> {
+        match external_path(&self.dir, self.coords, x, z) {
+            Ok(ref path) if path.exists() => fs::unlink(path),
+            _ => Ok(())
+        }
+    }
+
+    /// Writes already compressed chunk data, as `compression`, and points
+    /// the chunk at it. Data too large for the region goes in an external
+    /// file, with only its compression type left in the region.
+    pub fn write_raw_chunk(&mut self, x: u8, z: u8, compression: u8, data: &[u8],
+                           timestamp: u32) -> IoResult<()> {
+        let count = (data.len() + 5 + SECTOR_SIZE - 1) / SECTOR_SIZE;
+        if count > 0xff {
+            let path = try!(external_path(&self.dir, self.coords, x, z));
+            try!(File::create(&path).write_all(data));
+            return self.write_sectors(x, z, compression | COMPRESSION_EXTERNAL, &[], timestamp);
+        }
+        try!(self.remove_external(x, z));
+        self.write_sectors(x, z, compression, data, timestamp)
+    }
+
+    /// Writes chunk data that fits the region into free sectors.
+    fn write_sectors(&mut self, x: u8, z: u8, compression: u8, data: &[u8],
+                     timestamp: u32) -> IoResult<()> {
+        let count = (data.len() + 5 + SECTOR_SIZE - 1) / SECTOR_SIZE;
+        let i = chunk_index(x, z);
+        self.free(i);
+        let start = self.allocate(count);
+
+        try!(self.file.seek((start * SECTOR_SIZE) as i64, SeekStyle::SeekSet));
+        try!(self.file.write_be_u32(data.len() as u32 + 1));
+        try!(self.file.write_u8(compression));
+        try!(self.file.write_all(data));
+        let padding = count * SECTOR_SIZE - data.len() - 5;
+        try!(self.file.write_all(repeat(0u8).take(padding).collect::<Vec<u8>>().as_slice()));
+
+        self.locations[i] = (start << 8 | count) as u32;
+        self.timestamps[i] = timestamp;
+        self.write_header(i)
+    }
+
+    /// Compresses a chunk's NBT tree with zlib and writes it.
+    pub fn write_chunk_nbt(&mut self, x: u8, z: u8, nbt: &Nbt) -> IoResult<()> {
+        self.write_chunk_nbt_with(x, z, nbt, COMPRESSION_ZLIB)
+    }
+
+    /// Compresses a chunk's NBT tree as `compression`, which has to be
+    /// gzip, zlib, none or LZ4, and writes it.
+    pub fn write_chunk_nbt_with(&mut self, x: u8, z: u8, nbt: &Nbt, compression: u8)
+                                -> IoResult<()> {
+        let mut data = Vec::new();
+        try!(nbt.to_writer(&mut data, ""));
+        let data = try!(compress(compression, data.as_slice()));
+        self.write_raw_chunk(x, z, compression, data.as_slice(), time::get_time().sec as u32)
+    }
+
+    /// Removes a chunk, so that it's generated again when next loaded.
+    pub fn remove_chunk(&mut self, x: u8, z: u8) -> IoResult<()> {
+        try!(self.remove_external(x, z));
+        let i = chunk_index(x, z);
+        self.free(i);
+        self.locations[i] = 0;
+        self.timestamps[i] = 0;
+        self.write_header(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::old_io::{ File, TempDir };
+    use std::old_io::fs::PathExtensions;
+
+    use super::*;
+    use super::SECTOR_SIZE;
+    use super::RegionError::MissingChunkError;
+
+    fn data(len: usize, seed: u8) -> Vec<u8> {
+        range(0, len).map(|i| i as u8 ^ seed).collect()
+    }
+
+    fn assert_chunk(region: &Region, x: u8, z: u8, data: &[u8]) {
+        let (compression, stored) = region.raw_chunk(x, z).unwrap();
+        assert_eq!(compression, COMPRESSION_NONE);
+        assert!(&*stored == data);
+    }
+
+    #[test]
+    fn rewrites_reuse_free_sectors() {
+        let dir = TempDir::new("hematite-region").unwrap();
+        let path = dir.path().join("r.0.0.mca");
+        let (small, large, other) = (data(100, 1), data(5000, 2), data(8000, 3));
+        {
+            let mut writer = RegionWriter::open(&path).unwrap();
+            writer.write_raw_chunk(0, 0, COMPRESSION_NONE, small.as_slice(), 1).unwrap();
+            writer.write_raw_chunk(1, 0, COMPRESSION_NONE, small.as_slice(), 2).unwrap();
+            // Too large for its old sector, so it moves to the end...
+            writer.write_raw_chunk(0, 0, COMPRESSION_NONE, large.as_slice(), 3).unwrap();
+            // ...and back into the first free sector once it shrinks.
+            writer.write_raw_chunk(0, 0, COMPRESSION_NONE, small.as_slice(), 4).unwrap();
+            // Which leaves a gap of two sectors for this one.
+            writer.write_raw_chunk(2, 0, COMPRESSION_NONE, other.as_slice(), 5).unwrap();
+            writer.remove_chunk(1, 0).unwrap();
+        }
+        // Reopening keeps the tables, and knows which sectors are taken.
+        {
+            let mut writer = RegionWriter::open(&path).unwrap();
+            writer.write_raw_chunk(3, 0, COMPRESSION_NONE, small.as_slice(), 6).unwrap();
+        }
+
+        let region = Region::open(&path).unwrap();
+        assert_eq!(region.location(0, 0), Ok((2, 1)));
+        assert_eq!(region.location(1, 0), Err(MissingChunkError));
+        assert_eq!(region.location(2, 0), Ok((4, 2)));
+        assert_eq!(region.location(3, 0), Ok((3, 1)));
+        assert_eq!(region.sector_count(), 6);
+        let timestamps: Vec<u32> = range(0, 4).map(|x| region.timestamp(x, 0)).collect();
+        assert_eq!(timestamps, vec![4, 0, 5, 6]);
+        assert_chunk(&region, 0, 0, small.as_slice());
+        assert_chunk(&region, 2, 0, other.as_slice());
+        assert_chunk(&region, 3, 0, small.as_slice());
+        assert_eq!(region.chunks().len(), 3);
+    }
+
+    #[test]
+    fn oversized_chunks_go_in_external_files() {
+        let dir = TempDir::new("hematite-region").unwrap();
+        let path = dir.path().join("r.1.-1.mca");
+        let external = dir.path().join("c.35.-28.mcc");
+        // More than 255 sectors.
+        let (large, small) = (data(256 * SECTOR_SIZE, 1), data(100, 2));
+
+        RegionWriter::open(&path).unwrap()
+            .write_raw_chunk(3, 4, COMPRESSION_NONE, large.as_slice(), 1).unwrap();
+        assert!(external.exists());
+        {
+            let region = Region::open(&path).unwrap();
+            let info = region.chunk_info(3, 4).unwrap();
+            assert_eq!(info.compression, COMPRESSION_NONE | COMPRESSION_EXTERNAL);
+            assert_eq!(info.compressed_len, large.len());
+            assert_eq!(info.sector_count, 1);
+            assert_chunk(&region, 3, 4, large.as_slice());
+        }
+
+        // Shrinking the chunk brings it back into the region.
+        RegionWriter::open(&path).unwrap()
+            .write_raw_chunk(3, 4, COMPRESSION_NONE, small.as_slice(), 2).unwrap();
+        assert!(!external.exists());
+        {
+            let region = Region::open(&path).unwrap();
+            assert_eq!(region.chunk_info(3, 4).unwrap().compression, COMPRESSION_NONE);
+            assert_chunk(&region, 3, 4, small.as_slice());
+        }
+
+        // And removing it takes its external file along.
+        let mut writer = RegionWriter::open(&path).unwrap();
+        writer.write_raw_chunk(3, 4, COMPRESSION_NONE, large.as_slice(), 3).unwrap();
+        assert!(external.exists());
+        writer.remove_chunk(3, 4).unwrap();
+        assert!(!external.exists());
+    }
+
+    #[test]
+    fn refuses_truncated_headers() {
+        let dir = TempDir::new("hematite-region").unwrap();
+        let path = dir.path().join("r.0.0.mca");
+        File::create(&path).write_all(&[0u8; SECTOR_SIZE]).unwrap();
+        assert!(RegionWriter::open(&path).is_err());
+    }
+}
+
+#[cfg(test)]
+mod bench {
+    use std::iter::repeat;
+    use std::old_io::TempDir;
+    use test::Bencher;
+
+    use minecraft::nbt::{ Compound, IntoNbt, Nbt };
+    use minecraft::nbt::List::CompoundList;
+    use super::*;
+
+    /// A full column of 16 sections at 0, 0, stored uncompressed so that
+    /// only parsing is measured.
+    fn region(dir: &TempDir) -> Region {
+        let array = |len: usize| -> Vec<u8> { repeat(1u8).take(len).collect() };
+        let sections = range(0, 16).map(|y| Compound::new()
+            .with("Y", y as i8)
+            .with("Blocks", array(4096))
+            .with("Data", array(2048))
+            .with("BlockLight", array(2048))
+            .with("SkyLight", array(2048))
+        ).collect();
+        let chunk: Nbt = Compound::new().with("Level", Compound::new()
+            .with("xPos", 0i32)
+            .with("zPos", 0i32)
+            .with("Biomes", array(256))
+            .with("Sections", CompoundList(sections))
+        ).into_nbt();
+        let path = dir.path().join("r.0.0.mca");
+        {
+            let mut writer = RegionWriter::open(&path).unwrap();
+            writer.write_chunk_nbt_with(0, 0, &chunk, COMPRESSION_NONE).unwrap();
+        }
+        Region::open(&path).unwrap()
+    }
+
+    #[bench]
+    fn chunk_blocks(b: &mut Bencher) {
+        let dir = TempDir::new("hematite-bench").unwrap();
+        let region = region(&dir);
+        b.iter(|| region.get_chunk_blocks(0, 0).unwrap());
+    }
+
+    #[bench]
+    fn chunk_nbt(b: &mut Bencher) {
+        let dir = TempDir::new("hematite-bench").unwrap();
+        let region = region(&dir);
+        b.iter(|| region.get_chunk_nbt(0, 0).unwrap());
+    }
 }
@@ -0,0 +1,283 @@
+//! Save folders: `level.dat` and the region files of each dimension.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fmt;
+use std::old_io::{ File, IoError, IoErrorKind };
+use std::old_io::fs::PathExtensions;
+
+use gfx;
+
+use chunk::{ BiomeId, Chunk, ChunkColumn, SIZE };
+use minecraft::nbt::{ Nbt, NbtError };
+use minecraft::region::{ Region, RegionResult };
+use minecraft::region::RegionError::{ MissingChunkError, OpenError };
+
+use self::WorldError::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End
+}
+
+/// Every dimension a save folder can have regions for.
+pub const DIMENSIONS: [Dimension; 3] = [Dimension::Overworld, Dimension::Nether, Dimension::End];
+
+impl Dimension {
+    /// The dimension a player's `Dimension` tag refers to.
+    pub fn from_id(id: i32) -> Option<Dimension> {
+        match id {
+            0 => Some(Dimension::Overworld),
+            -1 => Some(Dimension::Nether),
+            1 => Some(Dimension::End),
+            _ => None
+        }
+    }
+
+    /// The dimension a player's `Dimension` tag refers to, in versions
+    /// that store it by name.
+    pub fn from_name(name: &str) -> Option<Dimension> {
+        match name {
+            "minecraft:overworld" => Some(Dimension::Overworld),
+            "minecraft:the_nether" => Some(Dimension::Nether),
+            "minecraft:the_end" => Some(Dimension::End),
+            _ => None
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        match *self {
+            Dimension::Overworld => 0,
+            Dimension::Nether => -1,
+            Dimension::End => 1
+        }
+    }
+
+    /// The directory holding the dimension's region files, in a save folder.
+    pub fn region_dir(&self) -> Path {
+        Path::new(match *self {
+            Dimension::Overworld => "region",
+            Dimension::Nether => "DIM-1/region",
+            Dimension::End => "DIM1/region"
+        })
+    }
+}
+
+/// The single player, as stored in `level.dat`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Player {
+    pub pos: [f64; 3],
+    /// Yaw and pitch, in degrees.
+    pub rotation: [f32; 2],
+    pub dimension: Dimension
+}
+
+/// The fields of `level.dat` the viewer and tools use.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LevelData {
+    pub name: String,
+    pub spawn: [i32; 3],
+    /// Ticks since the world was created.
+    pub time: i64,
+    /// Servers keep players in their own files, so there might be none.
+    pub player: Option<Player>
+}
+
+/// Why a save folder couldn't be opened.
+#[derive(Clone, PartialEq, Debug)]
+pub enum WorldError {
+    LevelReadError(IoError),
+    LevelNbtError(NbtError),
+    /// A tag `level.dat` needs is missing, or has the wrong type.
+    LevelFieldError(String)
+}
+
+pub type WorldResult<T> = Result<T, WorldError>;
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelReadError(ref e) => write!(f, "couldn't read level.dat: {}", e),
+            LevelNbtError(ref e) => write!(f, "invalid level.dat NBT: {}", e),
+            LevelFieldError(ref path) => write!(f, "missing or invalid field {}", path)
+        }
+    }
+}
+
+impl Error for WorldError {
+    fn description(&self) -> &str {
+        match *self {
+            LevelReadError(..) => "couldn't read level.dat",
+            LevelNbtError(..) => "invalid level.dat NBT",
+            LevelFieldError(..) => "missing or invalid field in level.dat"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            LevelReadError(ref e) => Some(e),
+            LevelNbtError(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl LevelData {
+    /// Reads the fields out of the tree of `level.dat`.
+    pub fn from_nbt(level: &Nbt) -> WorldResult<LevelData> {
+        fn field<'a>(level: &'a Nbt, path: &str) -> WorldResult<&'a Nbt> {
+            let tag = match level.lookup(path) {
+                Ok(Some(t)) => t.tag(),
+                _ => None
+            };
+            tag.ok_or_else(|| LevelFieldError(path.to_string()))
+        }
+        let int = |path: &str| -> WorldResult<i32> {
+            try!(field(level, path)).as_int().ok_or_else(|| LevelFieldError(path.to_string()))
+        };
+
+        let player = match field(level, "Data.Player") {
+            Ok(player) => {
+                let pos = player.get("Pos").and_then(|pos| pos.as_double_list());
+                let rotation = player.get("Rotation").and_then(|rot| rot.as_float_list());
+                // Older versions have no Dimension for the overworld.
+                let dimension = match player.get("Dimension") {
+                    None => Some(Dimension::Overworld),
+                    Some(&Nbt::Int(id)) => Dimension::from_id(id),
+                    Some(&Nbt::NbtString(ref name)) => Dimension::from_name(name.as_slice()),
+                    Some(_) => None
+                };
+                let dimension = try!(dimension.ok_or_else(|| {
+                    LevelFieldError("Data.Player.Dimension".to_string())
+                }));
+                match (pos, rotation) {
+                    (Some(pos), Some(rot)) if pos.len() == 3 && rot.len() == 2 => Some(Player {
+                        pos: [pos[0], pos[1], pos[2]],
+                        rotation: [rot[0], rot[1]],
+                        dimension: dimension
+                    }),
+                    _ => return Err(LevelFieldError("Data.Player".to_string()))
+                }
+            }
+            Err(_) => None
+        };
+
+        Ok(LevelData {
+            name: field(level, "Data.LevelName").ok().and_then(|name| name.as_string())
+                .unwrap_or("").to_string(),
+            spawn: [
+                try!(int("Data.SpawnX")),
+                try!(int("Data.SpawnY")),
+                try!(int("Data.SpawnZ"))
+            ],
+            time: field(level, "Data.Time").ok().and_then(|time| time.as_long()).unwrap_or(0),
+            player: player
+        })
+    }
+}
+
+/// A save folder, opening the region files of its dimensions as they're
+/// first needed.
+pub struct World {
+    path: Path,
+    level: LevelData,
+    dimensions: Vec<Dimension>,
+    /// Regions opened so far, `None` for those that don't exist.
+    regions: RefCell<HashMap<(Dimension, i32, i32), Option<Region>>>
+}
+
+impl World {
+    /// Opens a save folder, reading its `level.dat`.
+    pub fn open(path: &Path) -> WorldResult<World> {
+        let level_gzip = try!(File::open(&path.join("level.dat")).read_to_end()
+            .map_err(LevelReadError));
+        let level = try!(Nbt::from_gzip(level_gzip.as_slice()).map_err(LevelNbtError));
+        let level = try!(LevelData::from_nbt(&level));
+
+        let dimensions = DIMENSIONS.iter()
+            .map(|&dim| dim)
+            .filter(|dim| path.join(dim.region_dir()).is_dir())
+            .collect();
+
+        Ok(World {
+            path: path.clone(),
+            level: level,
+            dimensions: dimensions,
+            regions: RefCell::new(HashMap::new())
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn level(&self) -> &LevelData {
+        &self.level
+    }
+
+    /// The dimensions that have a region directory.
+    pub fn dimensions(&self) -> &[Dimension] {
+        self.dimensions.as_slice()
+    }
+
+    /// Closes the regions of a dimension that `keep` returns false for,
+    /// given their region coordinates. They're opened again if needed.
+    pub fn evict_regions<F>(&self, dim: Dimension, mut keep: F)
+        where F: FnMut(i32, i32) -> bool
+    {
+        let mut regions = self.regions.borrow_mut();
+        let evicted: Vec<_> = regions.keys()
+            .filter(|&&(d, region_x, region_z)| d == dim && !keep(region_x, region_z))
+            .map(|&key| key)
+            .collect();
+        for key in evicted.iter() {
+            regions.remove(key);
+        }
+    }
+
+    /// Calls `f` with the region holding a chunk, and the chunk's
+    /// position within it.
+    fn with_region<T, F>(&self, dim: Dimension, cx: i32, cz: i32, f: F) -> RegionResult<T>
+        where F: FnOnce(&Region, u8, u8) -> RegionResult<T>
+    {
+        let (region_x, region_z) = (cx >> 5, cz >> 5);
+        let mut regions = self.regions.borrow_mut();
+        let region = match regions.entry((dim, region_x, region_z)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = self.path.join(dim.region_dir())
+                    .join(format!("r.{}.{}.mca", region_x, region_z));
+                entry.insert(match Region::open(&path) {
+                    Ok(region) => Some(region),
+                    Err(IoError { kind: IoErrorKind::FileNotFound, .. }) => None,
+                    Err(e) => return Err(OpenError(e))
+                })
+            }
+        };
+        match *region {
+            Some(ref region) => f(region, (cx & 31) as u8, (cz & 31) as u8),
+            None => Err(MissingChunkError)
+        }
+    }
+
+    /// Reads the whole NBT tree of the chunk at absolute chunk coordinates.
+    pub fn chunk_nbt(&self, dim: Dimension, cx: i32, cz: i32) -> RegionResult<Nbt> {
+        self.with_region(dim, cx, cz, |region, x, z| region.get_chunk_nbt(x, z))
+    }
+
+    /// Reads the chunk column at absolute chunk coordinates.
+    pub fn chunk_column<R: gfx::Resources>(&self, dim: Dimension, cx: i32, cz: i32)
+                                           -> RegionResult<ChunkColumn<R>> {
+        self.with_region(dim, cx, cz, |region, x, z| region.get_chunk_column(x, z))
+    }
+
+    /// Reads the chunks and biomes of the column at absolute chunk coordinates.
+    pub fn chunk_blocks(&self, dim: Dimension, cx: i32, cz: i32)
+                        -> RegionResult<(Vec<Chunk>, [[BiomeId; SIZE]; SIZE])> {
+        self.with_region(dim, cx, cz, |region, x, z| region.get_chunk_blocks(x, z))
+    }
+}
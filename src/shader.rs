@@ -64,6 +64,7 @@ impl Clone for Vertex {
 
 pub struct Buffer<R: gfx::Resources> {
     batch: gfx::batch::RefBatch<ShaderParam<R>>,
+    handle: gfx::BufferHandle<R, Vertex>
 }
 
 pub struct Renderer<D: Device> {
@@ -125,8 +126,9 @@ impl<R: gfx::device::Resources, C: gfx::device::draw::CommandBuffer<R>, D: gfx::
     pub fn create_buffer(&mut self, data: &[Vertex]) -> Buffer<D::Resources> {
         let buf = self.graphics.device.create_buffer(data.len(), gfx::BufferUsage::Static);
         self.graphics.device.update_buffer(&buf, data, 0);
-        let mesh = gfx::Mesh::from_format(buf, data.len() as u32);
+        let mesh = gfx::Mesh::from_format(buf.clone(), data.len() as u32);
         Buffer {
+            handle: buf,
             batch: self.graphics.make_batch(
                     &self.prog,
                     self.params.clone(),
@@ -137,6 +139,11 @@ impl<R: gfx::device::Resources, C: gfx::device::draw::CommandBuffer<R>, D: gfx::
         }
     }
 
+    /// Frees the vertices of a buffer that won't be rendered anymore.
+    pub fn delete_buffer(&mut self, buffer: Buffer<D::Resources>) {
+        self.graphics.device.delete_buffer(buffer.handle);
+    }
+
     pub fn render(&mut self, buffer: &mut Buffer<D::Resources>) {
         buffer.batch.params = self.params.clone();
         self.graphics.draw(&buffer.batch, &self.frame).unwrap();
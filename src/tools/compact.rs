@@ -0,0 +1,131 @@
+//! Rewrites the region files of a world with their chunks packed in
+//! order, dropping the sectors left free by chunks that were rewritten.
+
+#![feature(env, old_io, old_path)]
+
+extern crate hematite;
+
+use std::env;
+use std::old_io::{ fs, IoError, IoErrorKind, IoResult, USER_RWX };
+use std::old_io::fs::PathExtensions;
+
+use hematite::minecraft::region::{ region_coords, Region, RegionWriter };
+use hematite::minecraft::region::RegionError::MissingChunkError;
+use hematite::minecraft::world::DIMENSIONS;
+
+/// Writes a packed copy of the region file at `from` to `to`, keeping
+/// chunk timestamps. Chunks that can't be read are left out and reported
+/// if `drop_unreadable` is set, and make the whole copy fail otherwise.
+fn compact(from: &Path, to: &Path, drop_unreadable: bool) -> IoResult<()> {
+    let region = try!(Region::open(from));
+    let mut writer = try!(RegionWriter::open(to));
+    for z in range(0, 32) {
+        for x in range(0, 32) {
+            match region.raw_chunk(x, z) {
+                Ok((compression, data)) => {
+                    let timestamp = region.timestamp(x, z);
+                    try!(writer.write_raw_chunk(x, z, compression, &*data, timestamp));
+                }
+                Err(MissingChunkError) => {}
+                Err(e) => {
+                    if !drop_unreadable {
+                        return Err(IoError {
+                            kind: IoErrorKind::OtherIoError,
+                            desc: "unreadable chunk, region left as it is",
+                            detail: Some(format!("chunk {}, {}: {}", x, z, e))
+                        });
+                    }
+                    println!("{}: dropping chunk {}, {}: {}", from.display(), x, z, e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compacts the regions of one dimension, returning their total size
+/// before and after.
+fn compact_dir(region_dir: &Path, out_dir: Option<&Path>) -> (u64, u64) {
+    // Without a copy, each region is written to a directory of its own,
+    // along with its external chunks, and moved back once it's complete.
+    let in_place = out_dir.is_none();
+    let tmp_dir = region_dir.join("compact.tmp");
+    let out_dir = out_dir.unwrap_or(&tmp_dir);
+    let listed = fs::mkdir_recursive(out_dir, USER_RWX).and_then(|()| fs::readdir(region_dir));
+    let paths = match listed {
+        Ok(paths) => paths,
+        Err(e) => {
+            println!("{}: {}", region_dir.display(), e);
+            env::set_exit_status(1);
+            return (0, 0);
+        }
+    };
+
+    let (mut old_total, mut new_total) = (0, 0);
+    for path in paths.iter() {
+        if region_coords(path).is_none() {
+            continue;
+        }
+        let to = out_dir.join(path.filename().unwrap());
+        let _ = fs::unlink(&to);
+        let compacted = compact(path, &to, !in_place).and_then(|()| {
+            let (old, new) = (try!(fs::stat(path)).size, try!(fs::stat(&to)).size);
+            if in_place {
+                for file in try!(fs::readdir(out_dir)).iter() {
+                    try!(fs::rename(file, &region_dir.join(file.filename().unwrap())));
+                }
+            }
+            Ok((old, new))
+        });
+        match compacted {
+            Ok((old, new)) => {
+                println!("{}: {} -> {} bytes, reclaimed {}",
+                         path.display(), old, new, old as i64 - new as i64);
+                old_total += old;
+                new_total += new;
+            }
+            Err(e) => {
+                println!("{}: {}", path.display(), e);
+                if in_place {
+                    for file in fs::readdir(out_dir).unwrap_or(vec![]).iter() {
+                        let _ = fs::unlink(file);
+                    }
+                }
+                env::set_exit_status(1);
+            }
+        }
+    }
+    if in_place {
+        let _ = fs::rmdir(out_dir);
+    }
+    (old_total, new_total)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (world, out) = match args.len() {
+        2 => (Path::new(&args[1]), None),
+        3 => (Path::new(&args[1]), Some(Path::new(&args[2]))),
+        _ => {
+            println!("Usage: ./hematite-compact <path/to/world> [<path/to/copy>]");
+            env::set_exit_status(2);
+            return;
+        }
+    };
+
+    let (mut old_total, mut new_total) = (0, 0);
+    for dim in DIMENSIONS.iter() {
+        let region_dir = world.join(dim.region_dir());
+        if !region_dir.is_dir() {
+            continue;
+        }
+        // A copy has the same layout of dimensions as the world.
+        let out_dir = out.as_ref().map(|out| out.join(dim.region_dir()));
+        let (old, new) = compact_dir(&region_dir, out_dir.as_ref());
+        old_total += old;
+        new_total += new;
+    }
+
+    println!("Reclaimed {} of {} bytes in total.",
+             old_total as i64 - new_total as i64, old_total);
+}
@@ -0,0 +1,199 @@
+//! Checks the region files of a world for corruption, and optionally
+//! writes a repaired copy of them.
+
+#![feature(collections, core, env, old_io, old_path)]
+
+extern crate hematite;
+
+use std::collections::{ HashMap, HashSet };
+use std::env;
+use std::old_io::{ fs, USER_RWX };
+use std::old_io::fs::PathExtensions;
+
+use hematite::minecraft::region::{ region_coords, Region, RegionWriter };
+use hematite::minecraft::region::RegionError::MissingChunkError;
+use hematite::minecraft::world::DIMENSIONS;
+
+/// Checks one region file, printing every problem found, and returns
+/// how many there were. If `out` is given, the chunks that could be read
+/// are copied to it as they're stored, with their timestamps, moved to
+/// their own slot if they were in another.
+fn check_region(path: &Path, region_x: i32, region_z: i32, out: Option<&Path>) -> usize {
+    let region = match Region::open(path) {
+        Ok(region) => region,
+        Err(e) => {
+            println!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    let mut problems = vec![];
+
+    // Which chunk each sector belongs to, to find the ones shared.
+    let mut owners: HashMap<usize, (u8, u8)> = HashMap::new();
+    let mut overlapping = HashSet::new();
+    for z in range(0, 32) {
+        for x in range(0, 32) {
+            let (start, count) = match region.location(x, z) {
+                Ok(location) => location,
+                Err(MissingChunkError) => continue,
+                Err(e) => {
+                    problems.push(format!("chunk {}, {}: {}", x, z, e));
+                    continue;
+                }
+            };
+            for sector in range(start, start + count) {
+                if sector < 2 {
+                    problems.push(format!("chunk {}, {}: overlaps the header", x, z));
+                    break;
+                }
+                match owners.insert(sector, (x, z)) {
+                    Some((ox, oz)) if !overlapping.contains(&(x, z)) => {
+                        problems.push(format!("chunk {}, {}: sector {} also used by chunk {}, {}",
+                                              x, z, sector, ox, oz));
+                        overlapping.insert((x, z));
+                        overlapping.insert((ox, oz));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Where each readable chunk goes in a repaired copy.
+    let mut slots: HashMap<(u8, u8), (u8, u8)> = HashMap::new();
+    let mut misplaced = vec![];
+    for z in range(0, 32) {
+        for x in range(0, 32) {
+            let nbt = match region.get_chunk_nbt(x, z) {
+                Ok(nbt) => nbt,
+                Err(MissingChunkError) => continue,
+                Err(e) => {
+                    problems.push(format!("chunk {}, {}: {}", x, z, e));
+                    continue;
+                }
+            };
+            let pos = |name: &str| match nbt.lookup(name) {
+                Ok(Some(t)) => t.tag().and_then(|t| t.as_int()),
+                _ => None
+            };
+            let (xpos, zpos) = match (pos("Level.xPos"), pos("Level.zPos")) {
+                (Some(xpos), Some(zpos)) => (xpos, zpos),
+                _ => {
+                    problems.push(format!("chunk {}, {}: missing Level.xPos or Level.zPos",
+                                          x, z));
+                    continue;
+                }
+            };
+            let (expected_x, expected_z) = (region_x * 32 + x as i32, region_z * 32 + z as i32);
+            if (xpos, zpos) == (expected_x, expected_z) {
+                slots.insert((x, z), (x, z));
+                continue;
+            }
+            problems.push(format!("chunk {}, {}: stored at {}, {} but its position is {}, {}",
+                                  x, z, expected_x, expected_z, xpos, zpos));
+            // Only chunks that belong to this region can be moved.
+            if xpos >> 5 == region_x && zpos >> 5 == region_z {
+                misplaced.push(((xpos & 31) as u8, (zpos & 31) as u8, x, z));
+            }
+        }
+    }
+    // Misplaced chunks only take slots no chunk in the right place holds.
+    for &(x, z, from_x, from_z) in misplaced.iter() {
+        if !slots.contains_key(&(x, z)) {
+            slots.insert((x, z), (from_x, from_z));
+        }
+    }
+
+    for problem in problems.iter() {
+        println!("{}: {}", path.display(), problem);
+    }
+
+    match out {
+        Some(out) => {
+            // The copy starts from nothing, not from an old copy.
+            let _ = fs::unlink(out);
+            let written = RegionWriter::open(out).and_then(|mut writer| {
+                let mut slots: Vec<_> = slots.into_iter().collect();
+                slots.sort();
+                for &((x, z), (from_x, from_z)) in slots.iter() {
+                    let (compression, data) = match region.raw_chunk(from_x, from_z) {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            println!("{}: chunk {}, {}: {}", path.display(), from_x, from_z, e);
+                            continue;
+                        }
+                    };
+                    let timestamp = region.timestamp(from_x, from_z);
+                    try!(writer.write_raw_chunk(x, z, compression, &*data, timestamp));
+                }
+                Ok(())
+            });
+            match written {
+                Ok(()) => {}
+                Err(e) => println!("{}: {}", out.display(), e)
+            }
+        }
+        None => {}
+    }
+
+    problems.len()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let usage = "Usage: ./hematite-fsck <path/to/world> [--repair <path/to/copy>]";
+    let (world, repair) = match args.len() {
+        2 => (Path::new(&args[1]), None),
+        4 if args[2] == "--repair" => (Path::new(&args[1]), Some(Path::new(&args[3]))),
+        _ => {
+            println!("{}", usage);
+            env::set_exit_status(2);
+            return;
+        }
+    };
+
+    let (mut regions, mut problems) = (0, 0);
+    for dim in DIMENSIONS.iter() {
+        let region_dir = world.join(dim.region_dir());
+        if !region_dir.is_dir() {
+            continue;
+        }
+        // The copy has the same layout of dimensions as the world.
+        let repair_dir = repair.as_ref().map(|repair| repair.join(dim.region_dir()));
+        match repair_dir {
+            Some(ref dir) => match fs::mkdir_recursive(dir, USER_RWX) {
+                Ok(()) => {}
+                Err(e) => {
+                    println!("{}: {}", dir.display(), e);
+                    env::set_exit_status(2);
+                    return;
+                }
+            },
+            None => {}
+        }
+
+        let paths = match fs::readdir(&region_dir) {
+            Ok(paths) => paths,
+            Err(e) => {
+                println!("{}: {}", region_dir.display(), e);
+                problems += 1;
+                continue;
+            }
+        };
+        for path in paths.iter() {
+            let (region_x, region_z) = match region_coords(path) {
+                Some(coords) => coords,
+                None => continue
+            };
+            let out = repair_dir.as_ref().map(|dir| dir.join(path.filename().unwrap()));
+            problems += check_region(path, region_x, region_z, out.as_ref());
+            regions += 1;
+        }
+    }
+
+    println!("Checked {} regions, found {} problems.", regions, problems);
+    if problems > 0 {
+        env::set_exit_status(1);
+    }
+}